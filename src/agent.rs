@@ -59,7 +59,18 @@ fn run_spec(spec: internal::InternalSpec) -> Result<(), String> {
         )
     })?;
 
-    setup_user(&spec.user)?;
+    if spec.rootless {
+        // The container was launched with `--userns=keep-id`, so the
+        // entrypoint already runs as the target uid/gid - there is no root
+        // to create the account or drop privileges from.
+        ensure_home_dir(&spec.user)?;
+    } else {
+        setup_user(&spec.user, spec.privilege_backend, spec.sudo_policy.as_ref())?;
+    }
+
+    // Resolve the shell while the passwd database is still fully readable,
+    // before we (maybe) drop down to the unprivileged uid.
+    let shell = select_shell(&spec);
 
     let mut env_map = build_base_env(&spec)?;
     env_map.extend(spec.env_overrides.clone());
@@ -69,7 +80,9 @@ fn run_spec(spec: internal::InternalSpec) -> Result<(), String> {
     );
     env_map.remove(SPEC_ENV);
 
-    drop_privileges(spec.user.uid, spec.user.gid)?;
+    if !spec.rootless {
+        drop_privileges(spec.user.uid, spec.user.gid, &spec.user.groups)?;
+    }
 
     if let Some(terminfo) = &spec.terminfo {
         install_terminfo(terminfo, &env_map)?;
@@ -77,29 +90,68 @@ fn run_spec(spec: internal::InternalSpec) -> Result<(), String> {
 
     let agent_path = current_agent_path();
     let script = build_shell_script(&spec, &agent_path);
-    let shell = select_shell(&spec);
+
+    if spec.no_new_privs {
+        set_no_new_privs()?;
+    }
+
+    if spec.pty {
+        crate::pty::run_in_pty(&shell, &script, &env_map);
+    }
     exec_shell(&shell, &script, &env_map)
 }
 
 fn build_base_env(spec: &internal::InternalSpec) -> Result<BTreeMap<String, String>, String> {
-    if let Some(persist) = &spec.persist_env
+    let env_map = if let Some(persist) = &spec.persist_env
         && persist.restore
         && persist.path.exists()
     {
         match load_env(&persist.path) {
-            Ok(env_map) => return Ok(env_map),
+            Ok(env_map) => env_map,
             Err(err) => {
                 eprintln!(
                     "Warning: failed to restore environment from {}: {err}",
                     persist.path.display()
                 );
+                env::vars().collect()
             }
         }
-    }
-    Ok(env::vars().collect())
+    } else {
+        env::vars().collect()
+    };
+    Ok(filter_env(env_map, spec.env_filter.as_ref()))
 }
 
-fn setup_user(user: &internal::UserSpec) -> Result<(), String> {
+/// Variable names kept even without an explicit `allow` entry, since a
+/// shell is barely usable without them.
+const DEFAULT_ALLOWED_ENV_VARS: &[&str] = &["HOME", "TERM", "LANG", "LC_ALL", "PATH", "TZ"];
+
+/// Apply `filter` to `env_map`: with no filter, inherit everything
+/// (backward compatible); with one, keep only `allow` plus the built-in
+/// defaults, then strip anything in `deny` regardless.
+fn filter_env(
+    env_map: BTreeMap<String, String>,
+    filter: Option<&internal::EnvFilter>,
+) -> BTreeMap<String, String> {
+    let Some(filter) = filter else {
+        return env_map;
+    };
+    env_map
+        .into_iter()
+        .filter(|(key, _)| {
+            let allowed = DEFAULT_ALLOWED_ENV_VARS.contains(&key.as_str())
+                || filter.allow.iter().any(|name| name == key);
+            let denied = filter.deny.iter().any(|name| name == key);
+            allowed && !denied
+        })
+        .collect()
+}
+
+fn setup_user(
+    user: &internal::UserSpec,
+    privilege_backend: internal::PrivilegeBackend,
+    sudo_policy: Option<&internal::SudoPolicy>,
+) -> Result<(), String> {
     let base_home = user
         .home
         .parent()
@@ -144,6 +196,27 @@ fn setup_user(user: &internal::UserSpec) -> Result<(), String> {
     ensure_passwd_entry(user)?;
     ensure_home_dir(user)?;
 
+    match privilege_backend {
+        internal::PrivilegeBackend::Pam => {
+            if let Err(err) = grant_via_pam(user) {
+                eprintln!("Warning: PAM privilege grant failed ({err}); falling back to sudoers");
+                grant_via_sudoers(user, sudo_policy)?;
+            }
+        }
+        internal::PrivilegeBackend::Sudoers => grant_via_sudoers(user, sudo_policy)?,
+    }
+
+    Ok(())
+}
+
+/// Grant `user` sudo by appending an entry to `/etc/sudoers`. With no
+/// `sudo_policy` this is the backward-compatible blanket
+/// `ALL=(ALL) NOPASSWD: ALL`; with one, the grant is scoped to exactly the
+/// `(runas)` user and command allowlist the policy describes.
+fn grant_via_sudoers(
+    user: &internal::UserSpec,
+    sudo_policy: Option<&internal::SudoPolicy>,
+) -> Result<(), String> {
     let sudoers_path = Path::new("/etc/sudoers");
     if sudoers_path.exists() {
         let sudo_name = lookup_username(user.uid).unwrap_or_else(|| user.name.clone());
@@ -166,7 +239,7 @@ fn setup_user(user: &internal::UserSpec) -> Result<(), String> {
             .append(true)
             .open(sudoers_path)
             .map_err(|err| format!("Error: failed to open /etc/sudoers: {err}"))?;
-        writeln!(sudoers, "{} ALL=(ALL) NOPASSWD: ALL", sudo_target)
+        writeln!(sudoers, "{}", sudoers_line(sudo_target, sudo_policy))
             .map_err(|err| format!("Error: failed to update /etc/sudoers: {err}"))?;
     } else {
         eprintln!("Warning: /etc/sudoers not found; skipping sudoers update");
@@ -175,6 +248,99 @@ fn setup_user(user: &internal::UserSpec) -> Result<(), String> {
     Ok(())
 }
 
+/// Render the sudoers line for `sudo_target`, either the blanket default or
+/// a policy-scoped `runas`/command allowlist.
+fn sudoers_line(sudo_target: &str, sudo_policy: Option<&internal::SudoPolicy>) -> String {
+    let Some(policy) = sudo_policy else {
+        return format!("{sudo_target} ALL=(ALL) NOPASSWD: ALL");
+    };
+    let commands: Vec<String> = policy
+        .commands
+        .iter()
+        .map(|cmd| {
+            if policy.arbitrary_args {
+                cmd.clone()
+            } else {
+                format!("{cmd} \"\"")
+            }
+        })
+        .collect();
+    format!(
+        "{sudo_target} ALL=({}) NOPASSWD: {}",
+        policy.runas,
+        commands.join(", ")
+    )
+}
+
+const PAM_SERVICE: &str = "giftwrap";
+
+/// Grant `user` elevated privileges by opening a PAM session for them,
+/// instead of editing `/etc/sudoers`. Scoped to this giftwrap invocation
+/// and leaves the container's system files untouched.
+fn grant_via_pam(user: &internal::UserSpec) -> Result<(), String> {
+    use std::ffi::CString;
+
+    let service = CString::new(PAM_SERVICE)
+        .map_err(|_| "Error: PAM service name contains a NUL byte".to_string())?;
+    let c_user = CString::new(user.name.as_str())
+        .map_err(|_| "Error: user name contains a NUL byte".to_string())?;
+
+    let mut pamh: *mut pam_sys::pam_handle = std::ptr::null_mut();
+    let rc =
+        unsafe { pam_sys::pam_start(service.as_ptr(), c_user.as_ptr(), std::ptr::null(), &mut pamh) };
+    if rc != pam_sys::PAM_SUCCESS {
+        return Err(format!("Error: pam_start failed (code {rc})"));
+    }
+
+    let rc = unsafe { pam_sys::pam_acct_mgmt(pamh, 0) };
+    if rc != pam_sys::PAM_SUCCESS {
+        unsafe {
+            pam_sys::pam_end(pamh, rc);
+        }
+        return Err(format!("Error: pam_acct_mgmt failed (code {rc})"));
+    }
+
+    let rc = unsafe { pam_sys::pam_open_session(pamh, 0) };
+    if rc != pam_sys::PAM_SUCCESS {
+        unsafe {
+            pam_sys::pam_end(pamh, rc);
+        }
+        return Err(format!("Error: pam_open_session failed (code {rc})"));
+    }
+
+    // The session is intentionally left open: `run_spec` execs into the
+    // user's shell right after this returns, so there is no later point in
+    // the process where we could call pam_close_session/pam_end.
+    Ok(())
+}
+
+/// Minimal FFI surface for libpam, declared by hand since giftwrap has no
+/// PAM crate dependency to draw on.
+#[allow(non_camel_case_types)]
+mod pam_sys {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    #[repr(C)]
+    pub struct pam_handle {
+        _private: [u8; 0],
+    }
+
+    pub const PAM_SUCCESS: c_int = 0;
+
+    #[link(name = "pam")]
+    extern "C" {
+        pub fn pam_start(
+            service_name: *const c_char,
+            user: *const c_char,
+            conversation: *const c_void,
+            pamh: *mut *mut pam_handle,
+        ) -> c_int;
+        pub fn pam_acct_mgmt(pamh: *mut pam_handle, flags: c_int) -> c_int;
+        pub fn pam_open_session(pamh: *mut pam_handle, flags: c_int) -> c_int;
+        pub fn pam_end(pamh: *mut pam_handle, pam_status: c_int) -> c_int;
+    }
+}
+
 fn run_command_ignore(cmd: &str, args: &[&str]) {
     let _ = Command::new(cmd).args(args).status();
 }
@@ -272,11 +438,7 @@ fn ensure_passwd_entry(user: &internal::UserSpec) -> Result<(), String> {
         return Ok(());
     }
 
-    let shell = if Path::new("/bin/bash").exists() {
-        "/bin/bash"
-    } else {
-        "/bin/sh"
-    };
+    let shell = default_login_shell();
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
@@ -357,8 +519,21 @@ fn chown_path(path: &Path, uid: u32, gid: u32) -> Result<(), String> {
     Ok(())
 }
 
-fn drop_privileges(uid: u32, gid: u32) -> Result<(), String> {
+fn drop_privileges(uid: u32, gid: u32, groups: &[u32]) -> Result<(), String> {
+    // Ordering is a hard invariant: the group set can only be changed while
+    // still root, and setgid must happen before setuid or it will fail once
+    // we've dropped the uid. `groups` was already resolved host-side via
+    // `getgrouplist` (see `resolve_groups` in main.rs), so this is just the
+    // setgroups half of the usual getgrouplist+setgroups initgroups pairing.
+    let gid_list: Vec<libc::gid_t> = groups.iter().map(|g| *g as libc::gid_t).collect();
     unsafe {
+        if libc::setgroups(gid_list.len(), gid_list.as_ptr()) != 0 {
+            return Err(format!(
+                "Error: failed to setgroups({:?}): {}",
+                groups,
+                std::io::Error::last_os_error()
+            ));
+        }
         if libc::setgid(gid as libc::gid_t) != 0 {
             return Err(format!(
                 "Error: failed to setgid({gid}): {}",
@@ -375,6 +550,21 @@ fn drop_privileges(uid: u32, gid: u32) -> Result<(), String> {
     Ok(())
 }
 
+/// Set `PR_SET_NO_NEW_PRIVS`, so no descendant of the shell we're about to
+/// exec - including via the `sudo` entry `setup_user` writes - can gain
+/// privileges through setuid/setgid binaries or file capabilities. Once set
+/// this bit is inherited across `fork`/`execve` and can never be cleared.
+fn set_no_new_privs() -> Result<(), String> {
+    let result = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if result != 0 {
+        return Err(format!(
+            "Error: failed to set PR_SET_NO_NEW_PRIVS: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
 fn install_terminfo(
     terminfo: &internal::TerminfoSpec,
     env_map: &BTreeMap<String, String>,
@@ -456,14 +646,39 @@ fn shell_escape(value: &str) -> String {
     format!("'{escaped}'")
 }
 
+/// Look up `uid`'s login shell (`pw_shell`) in the passwd database.
+fn lookup_shell(uid: u32) -> Option<String> {
+    unsafe {
+        let pwd = libc::getpwuid(uid as libc::uid_t);
+        if pwd.is_null() {
+            return None;
+        }
+        let shell = std::ffi::CStr::from_ptr((*pwd).pw_shell)
+            .to_string_lossy()
+            .into_owned();
+        if shell.is_empty() { None } else { Some(shell) }
+    }
+}
+
 fn select_shell(spec: &internal::InternalSpec) -> String {
     if let Some(shell) = &spec.shell {
         return shell.clone();
     }
+    if let Some(shell) = lookup_shell(spec.user.uid)
+        && Path::new(&shell).exists()
+    {
+        return shell;
+    }
+    default_login_shell().to_string()
+}
+
+/// Fallback shell used when neither an explicit override nor the passwd
+/// entry's `pw_shell` is available.
+fn default_login_shell() -> &'static str {
     if Path::new("/bin/bash").exists() {
-        "/bin/bash".to_string()
+        "/bin/bash"
     } else {
-        "/bin/sh".to_string()
+        "/bin/sh"
     }
 }
 
@@ -494,7 +709,9 @@ fn load_env(path: &Path) -> Result<BTreeMap<String, String>, String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{group_state, passwd_state};
+    use super::{filter_env, group_state, lookup_shell, passwd_state, sudoers_line};
+    use crate::internal::{EnvFilter, SudoPolicy};
+    use std::collections::BTreeMap;
 
     #[test]
     fn group_state_detects_root_and_gid() {
@@ -511,4 +728,89 @@ mod tests {
         assert!(has_uid);
         assert!(has_root);
     }
+
+    #[test]
+    fn lookup_shell_reads_pw_shell_for_current_uid() {
+        let uid = unsafe { libc::getuid() };
+        let shell = lookup_shell(uid).expect("expected a pw_shell for the current uid");
+        assert!(!shell.is_empty());
+    }
+
+    #[test]
+    fn lookup_shell_returns_none_for_unassigned_uid() {
+        assert_eq!(lookup_shell(u32::MAX), None);
+    }
+
+    #[test]
+    fn filter_env_inherits_everything_with_no_filter() {
+        let mut env_map = BTreeMap::new();
+        env_map.insert("LD_PRELOAD".to_string(), "evil.so".to_string());
+        assert_eq!(filter_env(env_map.clone(), None), env_map);
+    }
+
+    #[test]
+    fn filter_env_keeps_defaults_and_allowlisted_names() {
+        let mut env_map = BTreeMap::new();
+        env_map.insert("HOME".to_string(), "/home/dev".to_string());
+        env_map.insert("MY_TOKEN".to_string(), "secret".to_string());
+        env_map.insert("LD_PRELOAD".to_string(), "evil.so".to_string());
+        let filter = EnvFilter {
+            allow: vec!["MY_TOKEN".to_string()],
+            deny: Vec::new(),
+        };
+
+        let filtered = filter_env(env_map, Some(&filter));
+
+        assert_eq!(filtered.get("HOME"), Some(&"/home/dev".to_string()));
+        assert_eq!(filtered.get("MY_TOKEN"), Some(&"secret".to_string()));
+        assert_eq!(filtered.get("LD_PRELOAD"), None);
+    }
+
+    #[test]
+    fn filter_env_deny_wins_over_default_allow() {
+        let mut env_map = BTreeMap::new();
+        env_map.insert("HOME".to_string(), "/home/dev".to_string());
+        let filter = EnvFilter {
+            allow: Vec::new(),
+            deny: vec!["HOME".to_string()],
+        };
+
+        let filtered = filter_env(env_map, Some(&filter));
+
+        assert_eq!(filtered.get("HOME"), None);
+    }
+
+    #[test]
+    fn sudoers_line_defaults_to_blanket_grant() {
+        assert_eq!(
+            sudoers_line("dev", None),
+            "dev ALL=(ALL) NOPASSWD: ALL"
+        );
+    }
+
+    #[test]
+    fn sudoers_line_scopes_to_policy_commands_with_no_args() {
+        let policy = SudoPolicy {
+            runas: "root".to_string(),
+            commands: vec!["/usr/bin/apt".to_string(), "/usr/bin/systemctl".to_string()],
+            arbitrary_args: false,
+        };
+        assert_eq!(
+            sudoers_line("dev", Some(&policy)),
+            "dev ALL=(root) NOPASSWD: /usr/bin/apt \"\", /usr/bin/systemctl \"\""
+        );
+    }
+
+    #[test]
+    fn sudoers_line_allows_arbitrary_args_when_set() {
+        let policy = SudoPolicy {
+            runas: "root".to_string(),
+            commands: vec!["/usr/bin/apt".to_string()],
+            arbitrary_args: true,
+        };
+        assert_eq!(
+            sudoers_line("dev", Some(&policy)),
+            "dev ALL=(root) NOPASSWD: /usr/bin/apt"
+        );
+    }
 }
@@ -1,5 +1,13 @@
+use std::collections::HashMap;
 use std::fmt;
 
+/// Bound on alias expansion recursion (an alias expanding to another alias,
+/// and so on). A fixed depth is simpler than tracking visited names and
+/// still catches the case that actually matters - `a -> b -> a` - without
+/// rejecting legitimate diamond-shaped reuse of the same alias from two
+/// different expansions.
+const MAX_ALIAS_DEPTH: u32 = 16;
+
 /// High-level action requested by CLI flags.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CliAction {
@@ -21,10 +29,24 @@ pub struct CliOptions {
     pub override_image: Option<String>,
     /// Rebuild the image before running.
     pub rebuild: bool,
+    /// Run rootless (`--userns=keep-id`) instead of privileged+root.
+    pub rootless: bool,
     /// Extra args supplied via --gw-extra-args.
     pub extra_args: Vec<String>,
     /// Runtime args provided before the `--` delimiter.
     pub runtime_args: Vec<String>,
+    /// Repeated `--gw-config=key=value`/`+key=value`/`-key` overrides, applied
+    /// in order above env overrides.
+    pub config_overrides: Vec<String>,
+    /// `--gw-config-file=PATH` forces a specific config file, bypassing
+    /// `discover_config`.
+    pub config_file: Option<String>,
+    /// `--gw-runtime=` overrides the `gw_runtime` config param, forcing a
+    /// specific container runtime backend (podman/docker/nerdctl).
+    pub runtime_override: Option<String>,
+    /// `--gw-no-auto-rebuild` disables the content-hash staleness check that
+    /// otherwise rebuilds the image even without `--gw-rebuild`.
+    pub no_auto_rebuild: bool,
 }
 
 /// User command captured after the `--` delimiter (or remaining args).
@@ -54,13 +76,123 @@ impl fmt::Display for CliError {
 
 impl std::error::Error for CliError {}
 
+/// Expand config-defined aliases (`alias_<name>` params, keyed here by the
+/// bare `<name>`) at the front of `args`, the way cargo resolves `[alias]`
+/// entries before its own argument parsing runs. Recognizes either a
+/// leading `--gw-alias=<name>` token or - when the first token isn't a
+/// `--gw-` flag at all - a bare token that exactly matches a configured
+/// alias name, and splices that alias's token list in where the consumed
+/// token was. The new leading token is then re-checked for further
+/// expansion, up to `MAX_ALIAS_DEPTH`, so `a -> b -> a` errors out instead
+/// of looping forever. Expansion only ever runs before `parse_args`, so the
+/// `--` delimiter and terminal-action handling inside `parse_args` see
+/// nothing different than if the user had typed the expanded form directly.
+pub fn expand_aliases(
+    args: &[String],
+    aliases: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>, CliError> {
+    let mut expanded = args.to_vec();
+    for _ in 0..=MAX_ALIAS_DEPTH {
+        let Some(first) = expanded.first() else {
+            return Ok(expanded);
+        };
+        let name = if let Some(rest) = first.strip_prefix("--gw-alias=") {
+            Some(rest.to_string())
+        } else if !first.starts_with("--gw-") && aliases.contains_key(first) {
+            Some(first.clone())
+        } else {
+            None
+        };
+        let Some(name) = name else {
+            return Ok(expanded);
+        };
+        let Some(tokens) = aliases.get(&name) else {
+            let candidates: Vec<String> = aliases.keys().cloned().collect();
+            return Err(unknown_token_error("alias", &name, &candidates));
+        };
+        expanded.splice(0..1, tokens.iter().cloned());
+    }
+    Err(CliError::new(format!(
+        "Error: alias expansion exceeded {MAX_ALIAS_DEPTH} levels (possible alias cycle)"
+    )))
+}
+
+/// `--gw-*` flags `parse_args` recognizes, plus `--gw-alias` (consumed by
+/// `expand_aliases`), bare (without any `=value` suffix), used as the
+/// candidate pool for "did you mean" suggestions.
+const KNOWN_FLAGS: &[&str] = &[
+    "--gw-print",
+    "--gw-ctx",
+    "--gw-print-image",
+    "--gw-use-ctx",
+    "--gw-img",
+    "--gw-rebuild",
+    "--gw-no-auto-rebuild",
+    "--gw-rootless",
+    "--gw-extra-args",
+    "--gw-config",
+    "--gw-config-file",
+    "--gw-runtime",
+    "--gw-show-config",
+    "--gw-help",
+    "--gw-alias",
+];
+
+/// Largest edit distance worth suggesting - cargo-style near-miss matching,
+/// not a fuzzy search; a distance beyond this is more likely an unrelated
+/// flag than a typo.
+const MAX_SUGGEST_DISTANCE: usize = 3;
+
+/// Build an "unknown X" error, appending a "Did you mean" suggestion when
+/// some candidate in `candidates` is within `MAX_SUGGEST_DISTANCE` edits of
+/// `token`.
+fn unknown_token_error(kind: &str, token: &str, candidates: &[String]) -> CliError {
+    match best_match(token, candidates) {
+        Some(suggestion) => CliError::new(format!(
+            "Error: unknown {kind} '{token}'. Did you mean '{suggestion}'?"
+        )),
+        None => CliError::new(format!("Error: unknown {kind} '{token}'")),
+    }
+}
+
+fn best_match(token: &str, candidates: &[String]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|candidate| (candidate, levenshtein_distance(token, candidate)))
+        .filter(|(_, distance)| *distance <= MAX_SUGGEST_DISTANCE)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(candidate, _)| candidate.clone())
+}
+
+/// Classic O(len(a)*len(b)) edit-distance, two-row rolling variant.
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0usize; b.len() + 1];
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
 pub fn parse_args(args: &[String]) -> Result<(CliOptions, UserCommand), CliError> {
     let mut action = CliAction::Run;
     let mut use_ctx = None;
     let mut override_image = None;
     let mut rebuild = false;
+    let mut no_auto_rebuild = false;
+    let mut rootless = false;
     let mut extra_args = Vec::new();
     let mut runtime_args = Vec::new();
+    let mut config_overrides = Vec::new();
+    let mut config_file = None;
+    let mut runtime_override = None;
 
     let mut idx = 0;
     let mut terminal_action = false;
@@ -84,17 +216,31 @@ pub fn parse_args(args: &[String]) -> Result<(CliOptions, UserCommand), CliError
             override_image = Some(rest.to_string());
         } else if arg == "--gw-rebuild" {
             rebuild = true;
+        } else if arg == "--gw-no-auto-rebuild" {
+            no_auto_rebuild = true;
+        } else if arg == "--gw-rootless" {
+            rootless = true;
         } else if let Some(rest) = arg.strip_prefix("--gw-extra-args=") {
             let parts = shell_words::split(rest).map_err(|err| {
                 CliError::new(format!("Error: failed to parse --gw-extra-args: {err}"))
             })?;
             extra_args.extend(parts);
+        } else if let Some(rest) = arg.strip_prefix("--gw-config=") {
+            config_overrides.push(rest.to_string());
+        } else if let Some(rest) = arg.strip_prefix("--gw-config-file=") {
+            config_file = Some(rest.to_string());
+        } else if let Some(rest) = arg.strip_prefix("--gw-runtime=") {
+            runtime_override = Some(rest.to_string());
         } else if arg == "--gw-show-config" {
             action = CliAction::ShowConfig;
             terminal_action = true;
         } else if arg == "--gw-help" {
             action = CliAction::Help;
             terminal_action = true;
+        } else {
+            let token = arg.split('=').next().unwrap_or(arg);
+            let candidates: Vec<String> = KNOWN_FLAGS.iter().map(|flag| flag.to_string()).collect();
+            return Err(unknown_token_error("flag", token, &candidates));
         }
 
         idx += 1;
@@ -124,8 +270,13 @@ pub fn parse_args(args: &[String]) -> Result<(CliOptions, UserCommand), CliError
             use_ctx,
             override_image,
             rebuild,
+            no_auto_rebuild,
+            rootless,
             extra_args,
             runtime_args,
+            config_overrides,
+            config_file,
+            runtime_override,
         },
         UserCommand { argv: user_cmd },
     ))
@@ -133,7 +284,10 @@ pub fn parse_args(args: &[String]) -> Result<(CliOptions, UserCommand), CliError
 
 #[cfg(test)]
 mod tests {
-    use super::{CliAction, CliOptions, UserCommand, parse_args};
+    use super::{
+        CliAction, CliOptions, UserCommand, expand_aliases, levenshtein_distance, parse_args,
+    };
+    use std::collections::HashMap;
 
     fn parse(args: &[&str]) -> (CliOptions, UserCommand) {
         let argv = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
@@ -155,8 +309,13 @@ mod tests {
         assert!(opts.use_ctx.is_none());
         assert!(opts.override_image.is_none());
         assert!(!opts.rebuild);
+        assert!(!opts.no_auto_rebuild);
+        assert!(!opts.rootless);
         assert!(opts.extra_args.is_empty());
         assert!(opts.runtime_args.is_empty());
+        assert!(opts.config_overrides.is_empty());
+        assert!(opts.config_file.is_none());
+        assert!(opts.runtime_override.is_none());
         assert!(cmd.argv.is_empty());
     }
 
@@ -242,6 +401,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_config_overrides_accumulate_in_order() {
+        let (opts, cmd) = parse(&[
+            "--gw-config=gw_container=other",
+            "--gw-config=+extra_args=one",
+            "--gw-config=-drop_me",
+            "--",
+            "cmd",
+        ]);
+        assert_eq!(
+            opts.config_overrides,
+            vec!["gw_container=other", "+extra_args=one", "-drop_me"]
+        );
+        assert_eq!(cmd.argv, vec!["cmd"]);
+    }
+
+    #[test]
+    fn parse_config_file_overrides_discovery() {
+        let (opts, cmd) = parse(&["--gw-config-file=/tmp/custom.conf", "--", "cmd"]);
+        assert_eq!(opts.config_file.as_deref(), Some("/tmp/custom.conf"));
+        assert_eq!(cmd.argv, vec!["cmd"]);
+    }
+
+    #[test]
+    fn parse_no_auto_rebuild_flag() {
+        let (opts, cmd) = parse(&["--gw-no-auto-rebuild", "--", "cmd"]);
+        assert!(opts.no_auto_rebuild);
+        assert_eq!(cmd.argv, vec!["cmd"]);
+    }
+
+    #[test]
+    fn parse_rootless_flag() {
+        let (opts, cmd) = parse(&["--gw-rootless", "--", "cmd"]);
+        assert!(opts.rootless);
+        assert_eq!(cmd.argv, vec!["cmd"]);
+    }
+
+    #[test]
+    fn parse_runtime_override_selects_backend() {
+        let (opts, cmd) = parse(&["--gw-runtime=docker", "--", "cmd"]);
+        assert_eq!(opts.runtime_override.as_deref(), Some("docker"));
+        assert_eq!(cmd.argv, vec!["cmd"]);
+    }
+
     #[test]
     fn parse_delimiter_splits_runtime_and_user_command() {
         let (opts, cmd) = parse(&[
@@ -264,4 +467,112 @@ mod tests {
         assert!(opts.runtime_args.is_empty());
         assert_eq!(cmd.argv, vec!["bash", "-lc", "true"]);
     }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn alias_map(pairs: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(name, tokens)| (name.to_string(), argv(tokens)))
+            .collect()
+    }
+
+    #[test]
+    fn expand_aliases_expands_bare_name() {
+        let aliases = alias_map(&[("ci", &["--gw-rebuild", "--gw-use-ctx=main"])]);
+        let expanded = expand_aliases(&argv(&["ci", "--", "make", "test"]), &aliases)
+            .expect("expand_aliases failed");
+        assert_eq!(
+            expanded,
+            argv(&[
+                "--gw-rebuild",
+                "--gw-use-ctx=main",
+                "--",
+                "make",
+                "test"
+            ])
+        );
+    }
+
+    #[test]
+    fn expand_aliases_expands_explicit_flag_form() {
+        let aliases = alias_map(&[("ci", &["--gw-rebuild"])]);
+        let expanded = expand_aliases(&argv(&["--gw-alias=ci", "--", "cmd"]), &aliases)
+            .expect("expand_aliases failed");
+        assert_eq!(expanded, argv(&["--gw-rebuild", "--", "cmd"]));
+    }
+
+    #[test]
+    fn expand_aliases_leaves_unrelated_args_untouched() {
+        let aliases = alias_map(&[("ci", &["--gw-rebuild"])]);
+        let expanded = expand_aliases(&argv(&["--gw-rebuild", "--", "cmd"]), &aliases)
+            .expect("expand_aliases failed");
+        assert_eq!(expanded, argv(&["--gw-rebuild", "--", "cmd"]));
+    }
+
+    #[test]
+    fn expand_aliases_errors_on_unknown_alias_flag() {
+        let aliases = alias_map(&[]);
+        let message = expand_aliases(&argv(&["--gw-alias=missing"]), &aliases)
+            .err()
+            .expect("expected expand_aliases to fail")
+            .to_string();
+        assert_eq!(message, "Error: unknown alias 'missing'");
+    }
+
+    #[test]
+    fn expand_aliases_errors_on_cycle() {
+        let aliases = alias_map(&[("a", &["b"]), ("b", &["a"])]);
+        let message = expand_aliases(&argv(&["a"]), &aliases)
+            .err()
+            .expect("expected expand_aliases to fail")
+            .to_string();
+        assert!(
+            message.contains("possible alias cycle"),
+            "unexpected error message: {message}"
+        );
+    }
+
+    #[test]
+    fn expand_aliases_suggests_a_close_alias_name() {
+        let aliases = alias_map(&[("test", &["--gw-extra-args=cargo test"])]);
+        let message = expand_aliases(&argv(&["--gw-alias=tes"]), &aliases)
+            .err()
+            .expect("expected expand_aliases to fail")
+            .to_string();
+        assert_eq!(message, "Error: unknown alias 'tes'. Did you mean 'test'?");
+    }
+
+    #[test]
+    fn parse_errors_on_unknown_flag() {
+        let message = parse_err(&["--gw-bogus"]);
+        assert_eq!(message, "Error: unknown flag '--gw-bogus'");
+    }
+
+    #[test]
+    fn parse_suggests_a_close_flag_name() {
+        let message = parse_err(&["--gw-rebuld"]);
+        assert_eq!(
+            message,
+            "Error: unknown flag '--gw-rebuld'. Did you mean '--gw-rebuild'?"
+        );
+    }
+
+    #[test]
+    fn parse_suggests_across_equals_value_flags() {
+        let message = parse_err(&["--gw-use-ctxx=deadbeef"]);
+        assert_eq!(
+            message,
+            "Error: unknown flag '--gw-use-ctxx'. Did you mean '--gw-use-ctx'?"
+        );
+    }
+
+    #[test]
+    fn levenshtein_distance_matches_known_cases() {
+        assert_eq!(levenshtein_distance("", ""), 0);
+        assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+        assert_eq!(levenshtein_distance("flaw", "lawn"), 2);
+    }
 }
@@ -4,6 +4,31 @@ use std::fmt;
 use std::path::Path;
 use std::path::PathBuf;
 
+/// Layer a resolved parameter's value came from, in increasing precedence
+/// order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    System,
+    User,
+    Repo,
+    Env,
+    CommandArg,
+}
+
+/// A resolved parameter value together with the file and layer that set it,
+/// so `--gw-show-config`-style tooling can explain where a value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotatedValue {
+    pub path: PathBuf,
+    pub value: Vec<String>,
+    pub source: ConfigSource,
+}
+
+/// `<environment>` is used as the `AnnotatedValue::path` for overrides that
+/// did not come from a file on disk.
+const ENV_PSEUDO_PATH: &str = "<environment>";
+
 /// Parsed configuration plus build-root discovery metadata.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Config {
@@ -13,10 +38,19 @@ pub struct Config {
     pub config_path: PathBuf,
     /// Raw parameter map after applying env overrides.
     pub params: HashMap<String, Vec<String>>,
+    /// Per-key provenance: which layer last set each resolved value.
+    pub provenance: HashMap<String, AnnotatedValue>,
     /// Optional UUID used to scope GW_USER_OPT_* overrides.
     pub uuid: Option<String>,
 }
 
+impl Config {
+    /// The layer that set `key`'s currently resolved value, if any.
+    pub fn source_of(&self, key: &str) -> Option<ConfigSource> {
+        self.provenance.get(key).map(|annotated| annotated.source)
+    }
+}
+
 #[derive(Debug)]
 pub struct ConfigError {
     message: String,
@@ -39,6 +73,7 @@ impl fmt::Display for ConfigError {
 impl std::error::Error for ConfigError {}
 
 const CONFIG_NAMES: [&str; 2] = [".giftwrap", "giftwrap"];
+const SYSTEM_CONFIG_PATH: &str = "/etc/giftwrap";
 const ENV_SET_PREFIX: &str = "GW_USER_OPT_SET_";
 const ENV_ADD_PREFIX: &str = "GW_USER_OPT_ADD_";
 const ENV_DEL_PREFIX: &str = "GW_USER_OPT_DEL_";
@@ -50,16 +85,87 @@ enum EnvOpt {
     Del,
 }
 
+/// Operation a config-file line applies to its key: `Set` (the default,
+/// bare `key value...`) replaces whatever came before it; `Add` (`add key
+/// value...`) extends it. Mirrors the `Set`/`Add`/`Del` trio already used by
+/// `GW_USER_OPT_*` env overrides, minus `Del` (there is no file syntax to
+/// remove a key a lower layer set).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ConfigOp {
+    Set,
+    Add,
+}
+
+/// A single parsed key, together with the operation that decides how it
+/// merges into lower layers: `Set` if any occurrence in this file replaced
+/// the value, `Add` if every occurrence only extended it. Layers (and includes within a layer) are merged key-by-key with
+/// this operation: `Set` replaces the accumulated value, `Add` extends it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ParsedValue {
+    op: ConfigOp,
+    values: Vec<String>,
+}
+
+/// Layers merge in increasing precedence: `/etc/giftwrap`, the user config,
+/// every `.giftwrap`/`giftwrap` found walking from `start_dir` up to `/`
+/// (farthest first, so the closest one wins), then env overrides.
 pub fn load_from(start_dir: &Path) -> Result<Config, ConfigError> {
-    let (root_dir, config_path) = discover_config(start_dir)?;
-    let mut params = parse_config(&config_path)?;
+    load_from_with_overrides(start_dir, None, &[])
+}
+
+/// Like `load_from`, but also accepts `config_file` (forces that file and
+/// bypasses `discover_config` entirely) and `cli_overrides` - `key=value`,
+/// `+key=value`, or `-key` strings applied last, above env overrides, the
+/// same way `--config` works in jj/rhg.
+pub fn load_from_with_overrides(
+    start_dir: &Path,
+    config_file: Option<&Path>,
+    cli_overrides: &[String],
+) -> Result<Config, ConfigError> {
+    let (root_dir, config_path, repo_layers) = match config_file {
+        Some(path) => {
+            let root_dir = path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("/"));
+            (root_dir.clone(), path.to_path_buf(), vec![(root_dir, path.to_path_buf())])
+        }
+        None => {
+            let repo_layers = discover_all_configs(start_dir)?;
+            let (root_dir, config_path) = repo_layers
+                .first()
+                .cloned()
+                .ok_or_else(|| ConfigError::new("Error: never found a config file"))?;
+            (root_dir, config_path, repo_layers)
+        }
+    };
+
+    let mut params = HashMap::new();
+    let mut provenance = HashMap::new();
+
+    if let Some(system_path) = discover_system_config() {
+        let layer = parse_config(&system_path)?;
+        merge_layer(&mut params, &mut provenance, layer, ConfigSource::System, &system_path);
+    }
+
+    if let Some(user_path) = discover_user_config() {
+        let layer = parse_config(&user_path)?;
+        merge_layer(&mut params, &mut provenance, layer, ConfigSource::User, &user_path);
+    }
+
+    for (_dir, layer_path) in repo_layers.iter().rev() {
+        let layer = parse_config(layer_path)?;
+        merge_layer(&mut params, &mut provenance, layer, ConfigSource::Repo, layer_path);
+    }
 
     let uuid = params
         .get("uuid")
         .and_then(|vals| vals.first())
         .map(|v| v.replace('-', ""));
 
-    apply_env_overrides(&mut params, uuid.as_deref())?;
+    apply_env_overrides(&mut params, &mut provenance, uuid.as_deref())?;
+    apply_cli_overrides(&mut params, &mut provenance, cli_overrides)?;
+    expand_params(&mut params)?;
 
     if !params.contains_key("gw_container") {
         return Err(ConfigError::new(format!(
@@ -78,33 +184,125 @@ pub fn load_from(start_dir: &Path) -> Result<Config, ConfigError> {
         root_dir,
         config_path,
         params,
+        provenance,
         uuid,
     })
 }
 
-fn discover_config(start_dir: &Path) -> Result<(PathBuf, PathBuf), ConfigError> {
+/// Merge one layer's parsed keys into the running `params`/`provenance`,
+/// applying each key's `ConfigOp` against whatever a lower-precedence layer
+/// already contributed.
+fn merge_layer(
+    params: &mut HashMap<String, Vec<String>>,
+    provenance: &mut HashMap<String, AnnotatedValue>,
+    layer: HashMap<String, ParsedValue>,
+    source: ConfigSource,
+    layer_path: &Path,
+) {
+    for (key, parsed) in layer {
+        match parsed.op {
+            ConfigOp::Set => {
+                params.insert(key.clone(), parsed.values);
+            }
+            ConfigOp::Add => {
+                params.entry(key.clone()).or_default().extend(parsed.values);
+            }
+        }
+        provenance.insert(
+            key.clone(),
+            AnnotatedValue {
+                path: layer_path.to_path_buf(),
+                value: params[&key].clone(),
+                source,
+            },
+        );
+    }
+}
+
+/// Locate the system-wide config shared by every giftwrap user on the host,
+/// layered beneath the user and repo configs.
+fn discover_system_config() -> Option<PathBuf> {
+    let candidate = PathBuf::from(SYSTEM_CONFIG_PATH);
+    candidate.is_file().then_some(candidate)
+}
+
+/// Locate the user-global config, `$XDG_CONFIG_HOME/giftwrap` or
+/// `~/.giftwrap`, which is layered beneath the repo config.
+fn discover_user_config() -> Option<PathBuf> {
+    if let Some(xdg) = env::var_os("XDG_CONFIG_HOME") {
+        let candidate = PathBuf::from(xdg).join("giftwrap");
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+    let home = env::var_os("HOME")?;
+    let candidate = PathBuf::from(home).join(".giftwrap");
+    candidate.is_file().then_some(candidate)
+}
+
+/// Walk from `start_dir` up to `/`, returning every directory that has a
+/// `.giftwrap`/`giftwrap` file, closest first. Errors as soon as a single
+/// directory has both names present, same as the single-layer lookup used
+/// to predate this.
+fn discover_all_configs(start_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>, ConfigError> {
     let mut cwd = start_dir
         .canonicalize()
         .map_err(|err| ConfigError::new(format!("Error: failed to resolve cwd: {err}")))?;
     let root = Path::new("/");
 
+    let mut found = Vec::new();
     while cwd != root {
-        for name in CONFIG_NAMES {
-            let candidate = cwd.join(name);
-            if candidate.is_file() {
-                return Ok((cwd, candidate));
+        let present: Vec<&str> = CONFIG_NAMES
+            .into_iter()
+            .filter(|name| cwd.join(name).is_file())
+            .collect();
+        match present.as_slice() {
+            [] => {}
+            [name] => found.push((cwd.clone(), cwd.join(name))),
+            _ => {
+                return Err(ConfigError::new(format!(
+                    "Error: both {} and {} exist. Please consolidate your configs in one of them.",
+                    cwd.join(present[0]).display(),
+                    cwd.join(present[1]).display(),
+                )));
             }
         }
-        let parent = cwd
-            .parent()
-            .ok_or_else(|| ConfigError::new("Error: never found a config file"))?;
-        cwd = parent.to_path_buf();
+        let parent = match cwd.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => break,
+        };
+        cwd = parent;
     }
 
-    Err(ConfigError::new("Error: never found a config file"))
+    Ok(found)
+}
+
+/// Locate the closest `.giftwrap`/`giftwrap` file walking up from
+/// `start_dir`, i.e. the build root. Kept as a thin wrapper over
+/// `discover_all_configs` for callers that only care about the nearest
+/// layer.
+fn discover_config(start_dir: &Path) -> Result<(PathBuf, PathBuf), ConfigError> {
+    discover_all_configs(start_dir)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ConfigError::new("Error: never found a config file"))
+}
+
+fn parse_config(config_path: &Path) -> Result<HashMap<String, ParsedValue>, ConfigError> {
+    let mut visited = Vec::new();
+    parse_config_file(config_path, &mut visited)
 }
 
-fn parse_config(config_path: &Path) -> Result<HashMap<String, Vec<String>>, ConfigError> {
+/// Parse `config_path`, recursively merging any `include <path>` (or
+/// `%include <path>`, accepted as an alias for familiarity) directives.
+/// `visited` holds the canonicalized path of every file currently being
+/// parsed (the include stack, not the full history), so a file that includes
+/// itself - directly or via a longer cycle - is rejected instead of
+/// recursing forever.
+fn parse_config_file(
+    config_path: &Path,
+    visited: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, ParsedValue>, ConfigError> {
     let content = std::fs::read_to_string(config_path).map_err(|err| {
         ConfigError::new(format!(
             "Error: failed to read config file {}: {err}",
@@ -112,7 +310,21 @@ fn parse_config(config_path: &Path) -> Result<HashMap<String, Vec<String>>, Conf
         ))
     })?;
 
-    let mut params = HashMap::new();
+    let canonical = config_path.canonicalize().map_err(|err| {
+        ConfigError::new(format!(
+            "Error: failed to resolve config file {}: {err}",
+            config_path.display()
+        ))
+    })?;
+    if visited.contains(&canonical) {
+        return Err(ConfigError::new(format!(
+            "Error: {}: include cycle detected",
+            config_path.display()
+        )));
+    }
+    visited.push(canonical);
+
+    let mut params: HashMap<String, ParsedValue> = HashMap::new();
     for (idx, raw_line) in content.lines().enumerate() {
         let line = raw_line.trim();
         if line.is_empty() || line.starts_with('#') {
@@ -121,7 +333,8 @@ fn parse_config(config_path: &Path) -> Result<HashMap<String, Vec<String>>, Conf
 
         let parts = shell_words::split(line).map_err(|err| {
             ConfigError::new(format!(
-                "Error: failed to parse config line {}: {err}",
+                "Error: {}:{}: failed to parse config line: {err}",
+                config_path.display(),
                 idx + 1
             ))
         })?;
@@ -130,16 +343,98 @@ fn parse_config(config_path: &Path) -> Result<HashMap<String, Vec<String>>, Conf
             continue;
         }
 
-        let key = parts[0].clone();
-        let values = parts[1..].to_vec();
-        params.insert(key, values);
+        let (op, rest) = if parts[0] == "add" {
+            (ConfigOp::Add, &parts[1..])
+        } else {
+            (ConfigOp::Set, &parts[..])
+        };
+
+        if rest.is_empty() {
+            visited.pop();
+            return Err(ConfigError::new(format!(
+                "Error: {}:{}: expected a key",
+                config_path.display(),
+                idx + 1
+            )));
+        }
+
+        let key = rest[0].clone();
+        let values = rest[1..].to_vec();
+
+        if key == "include" || key == "%include" {
+            if values.len() != 1 {
+                visited.pop();
+                return Err(ConfigError::new(format!(
+                    "Error: {}:{}: include requires exactly one path argument",
+                    config_path.display(),
+                    idx + 1
+                )));
+            }
+            let include_path = resolve_include_path(config_path, &values[0]);
+            if !include_path.is_file() {
+                visited.pop();
+                return Err(ConfigError::new(format!(
+                    "Error: {}:{}: included file {} not found",
+                    config_path.display(),
+                    idx + 1,
+                    include_path.display()
+                )));
+            }
+            let included = match parse_config_file(&include_path, visited) {
+                Ok(included) => included,
+                Err(err) => {
+                    visited.pop();
+                    return Err(err);
+                }
+            };
+            for (included_key, included_value) in included {
+                apply_parsed(&mut params, included_key, included_value.op, included_value.values);
+            }
+            continue;
+        }
+
+        apply_parsed(&mut params, key, op, values);
     }
 
+    visited.pop();
     Ok(params)
 }
 
+/// Record a single key's operation against what this file (including its
+/// own includes) has accumulated for that key so far: `Set` replaces,
+/// `Add` extends.
+fn apply_parsed(params: &mut HashMap<String, ParsedValue>, key: String, op: ConfigOp, values: Vec<String>) {
+    match op {
+        ConfigOp::Set => {
+            params.insert(key, ParsedValue { op, values });
+        }
+        ConfigOp::Add => match params.get_mut(&key) {
+            Some(existing) => {
+                existing.values.extend(values);
+            }
+            None => {
+                params.insert(key, ParsedValue { op, values });
+            }
+        },
+    }
+}
+
+/// Resolve an `include` directive's path relative to the directory of the
+/// file it appeared in, so includes are portable across checkouts.
+fn resolve_include_path(including_file: &Path, raw: &str) -> PathBuf {
+    let candidate = PathBuf::from(raw);
+    if candidate.is_absolute() {
+        return candidate;
+    }
+    including_file
+        .parent()
+        .map(|dir| dir.join(&candidate))
+        .unwrap_or(candidate)
+}
+
 fn apply_env_overrides(
     params: &mut HashMap<String, Vec<String>>,
+    provenance: &mut HashMap<String, AnnotatedValue>,
     uuid: Option<&str>,
 ) -> Result<(), ConfigError> {
     for (key, value) in env::vars() {
@@ -150,18 +445,35 @@ fn apply_env_overrides(
         match op {
             EnvOpt::Del => {
                 params.remove(&opt);
+                provenance.remove(&opt);
             }
             EnvOpt::Add => {
                 let parts = shell_words::split(&value).map_err(|err| {
                     ConfigError::new(format!("Error: failed to parse env override {key}: {err}"))
                 })?;
-                params.entry(opt).or_default().extend(parts);
+                params.entry(opt.clone()).or_default().extend(parts);
+                provenance.insert(
+                    opt.clone(),
+                    AnnotatedValue {
+                        path: PathBuf::from(ENV_PSEUDO_PATH),
+                        value: params[&opt].clone(),
+                        source: ConfigSource::Env,
+                    },
+                );
             }
             EnvOpt::Set => {
                 let parts = shell_words::split(&value).map_err(|err| {
                     ConfigError::new(format!("Error: failed to parse env override {key}: {err}"))
                 })?;
-                params.insert(opt, parts);
+                params.insert(opt.clone(), parts.clone());
+                provenance.insert(
+                    opt,
+                    AnnotatedValue {
+                        path: PathBuf::from(ENV_PSEUDO_PATH),
+                        value: parts,
+                        source: ConfigSource::Env,
+                    },
+                );
             }
         }
     }
@@ -192,9 +504,178 @@ fn handle_env_opt(key: &str, uuid: Option<&str>) -> Option<(EnvOpt, String)> {
     Some((op, rest[expected.len()..].to_string()))
 }
 
+/// `<command-line>` is used as the `AnnotatedValue::path` for `--gw-config`
+/// overrides, which don't come from a file on disk either.
+const CLI_PSEUDO_PATH: &str = "<command-line>";
+
+/// Apply `--gw-config` overrides in order, above env overrides. Each entry
+/// is `key=value` (replace), `+key=value` (append, value split with
+/// `shell_words`), or `-key` (delete) - the same trio `GW_USER_OPT_*`
+/// expresses through env var name prefixes.
+fn apply_cli_overrides(
+    params: &mut HashMap<String, Vec<String>>,
+    provenance: &mut HashMap<String, AnnotatedValue>,
+    cli_overrides: &[String],
+) -> Result<(), ConfigError> {
+    for raw in cli_overrides {
+        let (op, key, value) = parse_cli_override(raw)?;
+
+        match op {
+            EnvOpt::Del => {
+                params.remove(&key);
+                provenance.remove(&key);
+            }
+            EnvOpt::Add => {
+                let parts = shell_words::split(value).map_err(|err| {
+                    ConfigError::new(format!("Error: failed to parse --gw-config {raw}: {err}"))
+                })?;
+                params.entry(key.clone()).or_default().extend(parts);
+                provenance.insert(
+                    key.clone(),
+                    AnnotatedValue {
+                        path: PathBuf::from(CLI_PSEUDO_PATH),
+                        value: params[&key].clone(),
+                        source: ConfigSource::CommandArg,
+                    },
+                );
+            }
+            EnvOpt::Set => {
+                let parts = shell_words::split(value).map_err(|err| {
+                    ConfigError::new(format!("Error: failed to parse --gw-config {raw}: {err}"))
+                })?;
+                params.insert(key.clone(), parts.clone());
+                provenance.insert(
+                    key,
+                    AnnotatedValue {
+                        path: PathBuf::from(CLI_PSEUDO_PATH),
+                        value: parts,
+                        source: ConfigSource::CommandArg,
+                    },
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse one `--gw-config` entry into an operation, key, and raw value text.
+fn parse_cli_override(raw: &str) -> Result<(EnvOpt, String, String), ConfigError> {
+    if let Some(key) = raw.strip_prefix('-') {
+        if key.is_empty() {
+            return Err(ConfigError::new(format!(
+                "Error: invalid --gw-config override: {raw}"
+            )));
+        }
+        return Ok((EnvOpt::Del, key.to_string(), String::new()));
+    }
+
+    let (op, body) = match raw.strip_prefix('+') {
+        Some(rest) => (EnvOpt::Add, rest),
+        None => (EnvOpt::Set, raw),
+    };
+
+    let (key, value) = body.split_once('=').ok_or_else(|| {
+        ConfigError::new(format!(
+            "Error: --gw-config override must be key=value, +key=value, or -key: {raw}"
+        ))
+    })?;
+    if key.is_empty() {
+        return Err(ConfigError::new(format!(
+            "Error: --gw-config override has an empty key: {raw}"
+        )));
+    }
+
+    Ok((op, key.to_string(), value.to_string()))
+}
+
+/// Expand `${NAME}`/`${NAME:-default}` references in every resolved value,
+/// against a snapshot of `params` taken before expansion so interpolation
+/// sees the final merged/overridden values rather than chaining through
+/// partially-expanded ones. `$$` is left as a literal `$`.
+fn expand_params(params: &mut HashMap<String, Vec<String>>) -> Result<(), ConfigError> {
+    let snapshot = params.clone();
+    for values in params.values_mut() {
+        for value in values.iter_mut() {
+            *value = expand_value(value, &snapshot)?;
+        }
+    }
+    Ok(())
+}
+
+/// Expand `${NAME}`/`${NAME:-default}`/`$$` references in a single value.
+/// A reference resolves against `params` first (only single-valued keys
+/// count - a multi-valued key is not a meaningful scalar to splice in),
+/// then against the process environment, then the `:-default` fallback if
+/// present; an unresolved reference with no default is a `ConfigError`.
+fn expand_value(value: &str, params: &HashMap<String, Vec<String>>) -> Result<String, ConfigError> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut result = String::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        if chars[idx] != '$' {
+            result.push(chars[idx]);
+            idx += 1;
+            continue;
+        }
+
+        if chars.get(idx + 1) == Some(&'$') {
+            result.push('$');
+            idx += 2;
+            continue;
+        }
+
+        if chars.get(idx + 1) != Some(&'{') {
+            result.push('$');
+            idx += 1;
+            continue;
+        }
+
+        let start = idx + 2;
+        let end = chars[start..]
+            .iter()
+            .position(|c| *c == '}')
+            .map(|offset| start + offset)
+            .ok_or_else(|| {
+                ConfigError::new(format!("Error: unterminated ${{...}} in config value {value:?}"))
+            })?;
+
+        let reference: String = chars[start..end].iter().collect();
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference.as_str(), None),
+        };
+
+        let resolved = resolve_reference(name, params)
+            .or_else(|| default.map(str::to_string))
+            .ok_or_else(|| {
+                ConfigError::new(format!(
+                    "Error: config value {value:?} references undefined variable \"{name}\""
+                ))
+            })?;
+        result.push_str(&resolved);
+
+        idx = end + 1;
+    }
+    Ok(result)
+}
+
+/// Resolve a `${NAME}` reference against already-defined single-valued
+/// params, falling back to the process environment.
+fn resolve_reference(name: &str, params: &HashMap<String, Vec<String>>) -> Option<String> {
+    if let Some(values) = params.get(name)
+        && let [single] = values.as_slice()
+    {
+        return Some(single.clone());
+    }
+    env::var(name).ok()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{apply_env_overrides, discover_config, load_from, parse_config};
+    use super::{
+        apply_cli_overrides, apply_env_overrides, discover_config, expand_value, load_from,
+        load_from_with_overrides, parse_config, ConfigSource,
+    };
     use std::collections::HashMap;
     use std::fs;
     use std::path::Path;
@@ -223,6 +704,17 @@ mod tests {
                 prior,
             }
         }
+
+        fn unset(key: &str) -> Self {
+            let prior = std::env::var(key).ok();
+            unsafe {
+                std::env::remove_var(key);
+            }
+            Self {
+                key: key.to_string(),
+                prior,
+            }
+        }
     }
 
     impl Drop for EnvVarGuard {
@@ -277,16 +769,44 @@ mod tests {
     }
 
     #[test]
-    fn discover_config_prefers_dot_giftwrap_over_giftwrap() {
+    fn discover_config_errors_on_ambiguous_config_files() {
         let temp = tempfile::tempdir().unwrap();
         write_config(temp.path(), ".giftwrap");
         write_config(temp.path(), "giftwrap");
 
-        let (root_dir, config_path) = discover_config(temp.path()).unwrap();
+        let err = discover_config(temp.path()).unwrap_err();
         let canonical_root = temp.path().canonicalize().unwrap();
 
-        assert_eq!(root_dir, canonical_root);
-        assert_eq!(config_path, canonical_root.join(".giftwrap"));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Error: both {} and {} exist. Please consolidate your configs in one of them.",
+                canonical_root.join(".giftwrap").display(),
+                canonical_root.join("giftwrap").display(),
+            )
+        );
+    }
+
+    #[test]
+    fn discover_config_errors_on_ambiguous_files_found_while_walking_up() {
+        let temp = tempfile::tempdir().unwrap();
+        write_config(temp.path(), ".giftwrap");
+        write_config(temp.path(), "giftwrap");
+
+        let nested = temp.path().join("child/grandchild");
+        fs::create_dir_all(&nested).unwrap();
+
+        let err = discover_config(&nested).unwrap_err();
+        let canonical_root = temp.path().canonicalize().unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Error: both {} and {} exist. Please consolidate your configs in one of them.",
+                canonical_root.join(".giftwrap").display(),
+                canonical_root.join("giftwrap").display(),
+            )
+        );
     }
 
     #[test]
@@ -316,27 +836,149 @@ empty_key
         let params = parse_config(&path).unwrap();
 
         assert_eq!(
-            params.get("gw_container").unwrap(),
-            &vec!["test".to_string()]
+            params.get("gw_container").unwrap().values,
+            vec!["test".to_string()]
         );
         assert_eq!(
-            params.get("extra_args").unwrap(),
-            &vec!["one two".to_string(), "three".to_string()]
+            params.get("extra_args").unwrap().values,
+            vec!["one two".to_string(), "three".to_string()]
         );
-        assert_eq!(params.get("empty_key").unwrap(), &Vec::<String>::new());
+        assert_eq!(params.get("empty_key").unwrap().values, Vec::<String>::new());
     }
 
     #[test]
-    fn parse_config_reports_line_number_on_error() {
+    fn parse_config_reports_path_and_line_number_on_error() {
         let temp = TempDir::new().unwrap();
         let path = temp.path().join("giftwrap");
         fs::write(&path, "gw_container test\nbad \"unterminated\n").unwrap();
 
         let err = parse_config(&path).unwrap_err();
 
-        assert!(err
-            .to_string()
-            .starts_with("Error: failed to parse config line 2:"));
+        assert!(err.to_string().starts_with(&format!(
+            "Error: {}:2: failed to parse config line:",
+            path.display()
+        )));
+    }
+
+    #[test]
+    fn parse_config_merges_included_file() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "base.giftwrap",
+            "extra_args \"1\"\ninclude sub/extra.giftwrap\n",
+        );
+        fs::create_dir(temp.path().join("sub")).unwrap();
+        write_config_contents(
+            &temp.path().join("sub"),
+            "extra.giftwrap",
+            "gw_container test\nextra_args \"2\"\n",
+        );
+
+        let params = parse_config(&temp.path().join("base.giftwrap")).unwrap();
+
+        assert_eq!(
+            params.get("gw_container").unwrap().values,
+            vec!["test".to_string()]
+        );
+        assert_eq!(params.get("extra_args").unwrap().values, vec!["2".to_string()]);
+    }
+
+    #[test]
+    fn parse_config_add_directive_extends_value() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("giftwrap");
+        fs::write(
+            &path,
+            "gw_container test\nextra_args one\nadd extra_args two\n",
+        )
+        .unwrap();
+
+        let params = parse_config(&path).unwrap();
+
+        assert_eq!(
+            params.get("extra_args").unwrap().values,
+            vec!["one".to_string(), "two".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_config_add_directive_extends_across_include() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "base.giftwrap",
+            "include sub.giftwrap\nadd extra_args local\n",
+        );
+        write_config_contents(temp.path(), "sub.giftwrap", "extra_args included\n");
+
+        let params = parse_config(&temp.path().join("base.giftwrap")).unwrap();
+
+        assert_eq!(
+            params.get("extra_args").unwrap().values,
+            vec!["included".to_string(), "local".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_config_later_line_overrides_included_value() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "base.giftwrap",
+            "include sub.giftwrap\ngw_container override\n",
+        );
+        write_config_contents(temp.path(), "sub.giftwrap", "gw_container test\n");
+
+        let params = parse_config(&temp.path().join("base.giftwrap")).unwrap();
+
+        assert_eq!(
+            params.get("gw_container").unwrap().values,
+            vec!["override".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_config_percent_include_is_alias_for_include() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "base.giftwrap",
+            "%include sub.giftwrap\n",
+        );
+        write_config_contents(temp.path(), "sub.giftwrap", "gw_container test\n");
+
+        let params = parse_config(&temp.path().join("base.giftwrap")).unwrap();
+
+        assert_eq!(
+            params.get("gw_container").unwrap().values,
+            vec!["test".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_config_reports_path_and_line_for_missing_include() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("giftwrap");
+        fs::write(&path, "gw_container test\ninclude missing.giftwrap\n").unwrap();
+
+        let err = parse_config(&path).unwrap_err();
+
+        assert!(err.to_string().starts_with(&format!(
+            "Error: {}:2: included file",
+            path.display()
+        )));
+    }
+
+    #[test]
+    fn parse_config_detects_include_cycle() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(temp.path(), "a.giftwrap", "include b.giftwrap\n");
+        write_config_contents(temp.path(), "b.giftwrap", "include a.giftwrap\n");
+
+        let err = parse_config(&temp.path().join("a.giftwrap")).unwrap_err();
+
+        assert!(err.to_string().ends_with("include cycle detected"));
     }
 
     #[test]
@@ -350,8 +992,9 @@ empty_key
         params.insert("suite5_param_x1c9".to_string(), vec!["old".to_string()]);
         params.insert("suite5_list_x1c9".to_string(), vec!["a".to_string()]);
         params.insert("suite5_remove_x1c9".to_string(), vec!["keep".to_string()]);
+        let mut provenance = HashMap::new();
 
-        apply_env_overrides(&mut params, None).unwrap();
+        apply_env_overrides(&mut params, &mut provenance, None).unwrap();
 
         assert_eq!(
             params.get("suite5_param_x1c9").unwrap(),
@@ -374,8 +1017,9 @@ empty_key
 
         let mut params = HashMap::new();
         params.insert("scoped_x1c9".to_string(), vec!["base".to_string()]);
+        let mut provenance = HashMap::new();
 
-        apply_env_overrides(&mut params, Some("abc123")).unwrap();
+        apply_env_overrides(&mut params, &mut provenance, Some("abc123")).unwrap();
 
         assert_eq!(
             params.get("scoped_x1c9").unwrap(),
@@ -394,8 +1038,9 @@ empty_key
 
         let mut params = HashMap::new();
         params.insert("scoped_x1c9".to_string(), vec!["base".to_string()]);
+        let mut provenance = HashMap::new();
 
-        apply_env_overrides(&mut params, None).unwrap();
+        apply_env_overrides(&mut params, &mut provenance, None).unwrap();
 
         assert_eq!(
             params.get("scoped_x1c9").unwrap(),
@@ -409,13 +1054,94 @@ empty_key
         let _guard = EnvVarGuard::set("GW_USER_OPT_SET_suite5_bad_x1c9", "\"unterminated");
 
         let mut params = HashMap::new();
-        let err = apply_env_overrides(&mut params, None).unwrap_err();
+        let mut provenance = HashMap::new();
+        let err = apply_env_overrides(&mut params, &mut provenance, None).unwrap_err();
 
         assert!(err
             .to_string()
             .starts_with("Error: failed to parse env override GW_USER_OPT_SET_suite5_bad_x1c9:"));
     }
 
+    #[test]
+    fn apply_cli_overrides_set_add_and_del() {
+        let mut params = HashMap::new();
+        params.insert("gw_container".to_string(), vec!["old".to_string()]);
+        params.insert("extra_args".to_string(), vec!["a".to_string()]);
+        params.insert("drop_me".to_string(), vec!["keep".to_string()]);
+        let mut provenance = HashMap::new();
+
+        let overrides = vec![
+            "gw_container=new".to_string(),
+            "+extra_args=b2 'b three'".to_string(),
+            "-drop_me".to_string(),
+        ];
+        apply_cli_overrides(&mut params, &mut provenance, &overrides).unwrap();
+
+        assert_eq!(
+            params.get("gw_container").unwrap(),
+            &vec!["new".to_string()]
+        );
+        assert_eq!(
+            params.get("extra_args").unwrap(),
+            &vec!["a".to_string(), "b2".to_string(), "b three".to_string()]
+        );
+        assert!(params.get("drop_me").is_none());
+        assert_eq!(
+            provenance.get("gw_container").unwrap().source,
+            ConfigSource::CommandArg
+        );
+    }
+
+    #[test]
+    fn apply_cli_overrides_rejects_malformed_entry() {
+        let mut params = HashMap::new();
+        let mut provenance = HashMap::new();
+
+        let err = apply_cli_overrides(&mut params, &mut provenance, &["no_equals".to_string()])
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Error: --gw-config override must be key=value, +key=value, or -key: no_equals"
+        );
+    }
+
+    #[test]
+    fn load_from_with_overrides_applies_cli_overrides_above_env() {
+        let _lock = lock_env();
+        let temp = TempDir::new().unwrap();
+        write_config_contents(temp.path(), "giftwrap", "gw_container test\n");
+        let _guard = EnvVarGuard::set("GW_USER_OPT_SET_extra_args", "from-env");
+
+        let overrides = vec!["extra_args=from-cli".to_string()];
+        let config = load_from_with_overrides(temp.path(), None, &overrides).unwrap();
+
+        assert_eq!(
+            config.params.get("extra_args").unwrap(),
+            &vec!["from-cli".to_string()]
+        );
+        assert_eq!(
+            config.source_of("extra_args"),
+            Some(ConfigSource::CommandArg)
+        );
+    }
+
+    #[test]
+    fn load_from_with_overrides_honors_explicit_config_file() {
+        let temp = TempDir::new().unwrap();
+        let elsewhere = temp.path().join("custom.conf");
+        fs::write(&elsewhere, "gw_container forced\n").unwrap();
+        // No .giftwrap/giftwrap anywhere under temp, so discover_config would fail.
+
+        let config = load_from_with_overrides(temp.path(), Some(&elsewhere), &[]).unwrap();
+
+        assert_eq!(config.config_path, elsewhere);
+        assert_eq!(
+            config.params.get("gw_container").unwrap(),
+            &vec!["forced".to_string()]
+        );
+    }
+
     #[test]
     fn load_from_applies_uuid_overrides_after_dash_stripping() {
         let _lock = lock_env();
@@ -452,6 +1178,59 @@ empty_key
         );
     }
 
+    #[test]
+    fn load_from_merges_repo_directories_closer_wins() {
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "giftwrap",
+            "gw_container parent\nadd extra_args from-parent\n",
+        );
+        let nested = temp.path().join("child");
+        fs::create_dir(&nested).unwrap();
+        write_config_contents(
+            &nested,
+            "giftwrap",
+            "gw_container child\nadd extra_args from-child\n",
+        );
+
+        let config = load_from(&nested).unwrap();
+
+        assert_eq!(
+            config.params.get("gw_container").unwrap(),
+            &vec!["child".to_string()]
+        );
+        assert_eq!(
+            config.params.get("extra_args").unwrap(),
+            &vec!["from-parent".to_string(), "from-child".to_string()]
+        );
+        assert_eq!(
+            config.provenance.get("gw_container").unwrap().path,
+            nested.canonicalize().unwrap().join("giftwrap")
+        );
+    }
+
+    #[test]
+    fn load_from_layers_user_config_beneath_repo() {
+        let _lock = lock_env();
+        let user_home = TempDir::new().unwrap();
+        write_config_contents(user_home.path(), ".giftwrap", "user_only value\n");
+        let _home_guard = EnvVarGuard::set("HOME", &user_home.path().to_string_lossy());
+        let _xdg_guard = EnvVarGuard::unset("XDG_CONFIG_HOME");
+
+        let repo = TempDir::new().unwrap();
+        write_config_contents(repo.path(), "giftwrap", "gw_container test\n");
+
+        let config = load_from(repo.path()).unwrap();
+
+        assert_eq!(
+            config.params.get("user_only").unwrap(),
+            &vec!["value".to_string()]
+        );
+        assert_eq!(config.source_of("user_only"), Some(ConfigSource::User));
+        assert_eq!(config.source_of("gw_container"), Some(ConfigSource::Repo));
+    }
+
     #[test]
     fn load_from_errors_on_prefix_conflict() {
         let temp = TempDir::new().unwrap();
@@ -468,4 +1247,99 @@ empty_key
             "Error: must specify at most one of prefix_cmd and prefix_cmd_quiet"
         );
     }
+
+    #[test]
+    fn expand_value_substitutes_param_then_env_and_literal_dollar() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::set("GW_EXPAND_TEST", "env-value");
+        let mut params = HashMap::new();
+        params.insert("project".to_string(), vec!["acme".to_string()]);
+
+        let expanded = expand_value(
+            "registry/${project}:${GW_EXPAND_TEST}$$lit",
+            &params,
+        )
+        .unwrap();
+
+        assert_eq!(expanded, "registry/acme:env-value$lit");
+    }
+
+    #[test]
+    fn expand_value_applies_default_when_undefined() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::unset("GW_EXPAND_MISSING");
+        let params = HashMap::new();
+
+        let expanded = expand_value("${GW_EXPAND_MISSING:-fallback}", &params).unwrap();
+
+        assert_eq!(expanded, "fallback");
+    }
+
+    #[test]
+    fn expand_value_ignores_multi_valued_param_and_falls_back_to_env() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::set("extra_args", "from-env");
+        let mut params = HashMap::new();
+        params.insert(
+            "extra_args".to_string(),
+            vec!["one".to_string(), "two".to_string()],
+        );
+
+        let expanded = expand_value("${extra_args}", &params).unwrap();
+
+        assert_eq!(expanded, "from-env");
+    }
+
+    #[test]
+    fn expand_value_errors_on_undefined_reference() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::unset("GW_EXPAND_MISSING");
+        let params = HashMap::new();
+
+        let err = expand_value("${GW_EXPAND_MISSING}", &params).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Error: config value \"${GW_EXPAND_MISSING}\" references undefined variable \"GW_EXPAND_MISSING\""
+        );
+    }
+
+    #[test]
+    fn load_from_expands_references_before_gw_container_check() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::unset("GW_EXPAND_PROJECT");
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "giftwrap",
+            "project acme\ngw_container registry/${project}:latest\n",
+        );
+
+        let config = load_from(temp.path()).unwrap();
+
+        assert_eq!(
+            config.params.get("gw_container").unwrap(),
+            &vec!["registry/acme:latest".to_string()]
+        );
+    }
+
+    #[test]
+    fn load_from_errors_when_expanded_gw_container_is_undefined() {
+        let _lock = lock_env();
+        let _guard = EnvVarGuard::unset("GW_EXPAND_UNDEFINED");
+        let temp = TempDir::new().unwrap();
+        write_config_contents(
+            temp.path(),
+            "giftwrap",
+            "gw_container ${GW_EXPAND_UNDEFINED}\n",
+        );
+
+        let err = load_from(temp.path()).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("references undefined variable \"GW_EXPAND_UNDEFINED\""),
+            "unexpected error message: {err}"
+        );
+    }
 }
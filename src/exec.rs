@@ -1,38 +1,1084 @@
 use std::fmt;
-use std::path::Path;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::mpsc;
+use std::time::Duration;
 
-use crate::internal::ContainerSpec;
-use crate::podman_cli;
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 
+use crate::internal::{BuildSpec, ContainerSpec};
+use crate::jobserver::OwnedJobserver;
+use crate::runtime::Backend;
+use crate::staleness::{self, StalenessError};
+
+/// Podman/docker/nerdctl convention: exit code 125 means the runtime
+/// command itself failed (bad flag, daemon error, stale cgroup path,
+/// "layer already being used" race, ...), as opposed to 126/127 (container
+/// command couldn't be exec'd) or the container's own exit code, which
+/// passes through untouched. Only 125 is worth retrying.
+const RUNTIME_FAILURE_EXIT_CODE: i32 = 125;
+
+/// Bounded attempts for the `run_container` retry loop.
+const RUN_RETRY_ATTEMPTS: u32 = 5;
+
+/// Starting backoff before the first retry; doubles each attempt.
+const RUN_RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Every way a runtime invocation can fail, each carrying enough context
+/// (the argv attempted, the process exit status, captured stderr) to make
+/// the failure diagnosable instead of a flat message - the same "wrap the
+/// operation with its context" idea fs-err applies to filesystem calls, but
+/// for container operations.
 #[derive(Debug)]
-pub struct ExecError {
-    message: String,
+pub enum ExecError {
+    /// `<runtime> build` exited non-zero.
+    BuildFailed {
+        command: Vec<String>,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// A container lifecycle operation (`run`/`create`/`start`/`stop`/
+    /// `kill`/`delete`/`exec`) or archive transfer (`save`/`load`) failed -
+    /// either the runtime rejected the spec/archive before anything launched
+    /// (`status: None`, `stderr` holding the rejection reason), or the
+    /// launched process exited non-zero.
+    RunFailed {
+        action: String,
+        command: Vec<String>,
+        status: Option<ExitStatus>,
+        stderr: String,
+    },
+    /// A read-only query (`image exists`/`inspect`/`port`) failed the same
+    /// two ways as `RunFailed`: a rejected/unparsable invocation
+    /// (`status: None`) or a non-zero exit.
+    ImageProbeFailed {
+        action: String,
+        command: Vec<String>,
+        status: Option<ExitStatus>,
+        stderr: String,
+    },
+    /// The runtime binary itself couldn't be launched (not on `PATH`, no
+    /// permission, ...). Chains the underlying `io::Error` via `source()`.
+    RuntimeNotFound {
+        action: String,
+        binary: String,
+        source: std::io::Error,
+    },
+    /// `build_image_if_stale` couldn't hash the build context (a file
+    /// vanished mid-walk, `context_dir` doesn't exist, ...). Chains the
+    /// underlying `StalenessError` via `source()`.
+    ContextHashFailed {
+        context_dir: PathBuf,
+        source: StalenessError,
+    },
 }
 
 impl ExecError {
-    fn new(message: impl Into<String>) -> Self {
-        Self {
-            message: message.into(),
+    pub(crate) fn not_found(action: &str, binary: &str, source: std::io::Error) -> Self {
+        ExecError::RuntimeNotFound {
+            action: action.to_string(),
+            binary: binary.to_string(),
+            source,
+        }
+    }
+
+    /// Build a `BuildFailed` from a failed `Command::output()` invocation,
+    /// capturing argv and stderr so `Display` reports what the engine
+    /// actually said instead of just an exit code - rust-runc does the
+    /// same thing when it buffers the runtime's stderr.
+    pub(crate) fn build_failed(binary: &str, args: &[String], output: &std::process::Output) -> Self {
+        ExecError::BuildFailed {
+            command: full_command(binary, args),
+            status: output.status,
+            stderr: captured_stderr(output),
+        }
+    }
+
+    pub(crate) fn run_failed(
+        action: &str,
+        binary: &str,
+        args: &[String],
+        output: &std::process::Output,
+    ) -> Self {
+        ExecError::RunFailed {
+            action: action.to_string(),
+            command: full_command(binary, args),
+            status: Some(output.status),
+            stderr: captured_stderr(output),
+        }
+    }
+
+    /// A `RunFailed` that never got as far as launching a process - e.g. an
+    /// invalid `ContainerSpec` rejected by `Backend::build_run_args`.
+    pub(crate) fn run_invalid(action: &str, message: impl Into<String>) -> Self {
+        ExecError::RunFailed {
+            action: action.to_string(),
+            command: Vec::new(),
+            status: None,
+            stderr: message.into(),
+        }
+    }
+
+    pub(crate) fn probe_failed(
+        action: &str,
+        binary: &str,
+        args: &[String],
+        output: &std::process::Output,
+    ) -> Self {
+        ExecError::ImageProbeFailed {
+            action: action.to_string(),
+            command: full_command(binary, args),
+            status: Some(output.status),
+            stderr: captured_stderr(output),
+        }
+    }
+
+    /// An `ImageProbeFailed` with no process status to report - e.g. output
+    /// this crate couldn't parse as the expected JSON shape.
+    pub(crate) fn probe_invalid(action: &str, message: impl Into<String>) -> Self {
+        ExecError::ImageProbeFailed {
+            action: action.to_string(),
+            command: Vec::new(),
+            status: None,
+            stderr: message.into(),
         }
     }
 }
 
+fn full_command(binary: &str, args: &[String]) -> Vec<String> {
+    let mut command = vec![binary.to_string()];
+    command.extend(args.iter().cloned());
+    command
+}
+
+fn captured_stderr(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+fn write_command_suffix(f: &mut fmt::Formatter<'_>, command: &[String]) -> fmt::Result {
+    if command.is_empty() {
+        Ok(())
+    } else {
+        write!(f, " (command: {})", command.join(" "))
+    }
+}
+
 impl fmt::Display for ExecError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            ExecError::BuildFailed {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "Error: runtime build failed (exit {})", format_exit_status(status))?;
+                if !stderr.is_empty() {
+                    write!(f, ": {stderr}")?;
+                }
+                write_command_suffix(f, command)
+            }
+            ExecError::RunFailed {
+                action,
+                command,
+                status,
+                stderr,
+            }
+            | ExecError::ImageProbeFailed {
+                action,
+                command,
+                status,
+                stderr,
+            } => {
+                match status {
+                    Some(status) => {
+                        write!(f, "Error: runtime {action} failed (exit {})", format_exit_status(status))?;
+                        if !stderr.is_empty() {
+                            write!(f, ": {stderr}")?;
+                        }
+                    }
+                    None => write!(f, "{stderr}")?,
+                }
+                write_command_suffix(f, command)
+            }
+            ExecError::RuntimeNotFound {
+                action,
+                binary,
+                source,
+            } => write!(f, "Error: failed to launch runtime {action} ({binary}): {source}"),
+            ExecError::ContextHashFailed { context_dir, source } => write!(
+                f,
+                "Error: failed to hash build context {}: {source}",
+                context_dir.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExecError::RuntimeNotFound { source, .. } => Some(source),
+            ExecError::ContextHashFailed { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub fn build_image(backend: &dyn Backend, spec: &BuildSpec) -> Result<(), ExecError> {
+    let args = backend.build_build_args(spec);
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("build", backend.binary_name(), err))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(ExecError::build_failed(backend.binary_name(), &args, &output))
+    }
+}
+
+/// Like `build_image`, but skips the rebuild entirely when nothing that
+/// would affect its output has changed since the last time it ran. Hashes
+/// every file under `spec.context_dir` together with `spec.build_args` and
+/// `spec.dockerfile` into a single digest, tags the image
+/// `<spec.image>:<digest>` (mirroring `select_image`'s `:sha`-style
+/// tagging convention), and short-circuits via `image_exists` when that tag
+/// is already present - only invoking `build_image` when the digest-tagged
+/// image doesn't exist yet. This is the same "compute whether work is
+/// needed from observable state" idea cargo-smart-release applies to
+/// release history, here applied to a build context instead of a git log.
+/// Returns the digest-tagged image reference a caller should run.
+pub fn build_image_if_stale(backend: &dyn Backend, spec: &BuildSpec) -> Result<String, ExecError> {
+    let digest = context_digest(spec).map_err(|source| ExecError::ContextHashFailed {
+        context_dir: spec.context_dir.clone(),
+        source,
+    })?;
+    let tagged_image = format!("{}:{digest}", spec.image);
+
+    if image_exists(backend, &tagged_image)? {
+        return Ok(tagged_image);
+    }
+
+    let mut tagged_spec = spec.clone();
+    tagged_spec.image = tagged_image.clone();
+    build_image(backend, &tagged_spec)?;
+    Ok(tagged_image)
+}
+
+/// blake3 digest of everything that can change what `build_image_if_stale`
+/// produces: every file under `spec.context_dir` (via
+/// `staleness::collect_context_files`, the same whole-tree walk
+/// `collect_build_inputs` uses before narrowing to a glob subset), plus
+/// `build_args` and the rendered `dockerfile` path - fields that affect the
+/// build without necessarily showing up as a `context_dir` file.
+fn context_digest(spec: &BuildSpec) -> Result<String, StalenessError> {
+    let files = staleness::collect_context_files(&spec.context_dir)?;
+    let content_digest = staleness::compute_digest(&spec.context_dir, &files)?;
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(content_digest.as_bytes());
+    hasher.update(b"\0");
+    for (key, value) in &spec.build_args {
+        hasher.update(key.as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\0");
+    }
+    if let Some(dockerfile) = &spec.dockerfile {
+        hasher.update(dockerfile.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Like `build_image`, but streams the runtime's combined stdout/stderr to
+/// `on_line` line-by-line as the build runs, instead of blocking silently
+/// until it finishes - so a TUI or logger can show layer-by-layer progress
+/// rather than a silent hang. `quiet` suppresses output entirely (`on_line`
+/// is never called) and falls back to `build_image`'s plain `output()` call.
+pub fn build_image_streaming(
+    backend: &dyn Backend,
+    spec: &BuildSpec,
+    quiet: bool,
+    mut on_line: impl FnMut(&str),
+) -> Result<(), ExecError> {
+    if quiet {
+        return build_image(backend, spec);
+    }
+
+    let args = backend.build_build_args(spec);
+    let mut child = Command::new(backend.binary_name())
+        .args(&args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| ExecError::not_found("build", backend.binary_name(), err))?;
+
+    let stdout = child.stdout.take().expect("build spawned with a piped stdout");
+    let stderr = child.stderr.take().expect("build spawned with a piped stderr");
+
+    let (tx, rx) = mpsc::channel::<String>();
+    let stdout_tx = tx.clone();
+    let stdout_thread = std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if stdout_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let stderr_thread = std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut captured = Vec::new();
+    for line in rx {
+        on_line(&line);
+        captured.push(line);
+    }
+
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+    let status = child
+        .wait()
+        .map_err(|err| ExecError::not_found("build", backend.binary_name(), err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ExecError::BuildFailed {
+            command: full_command(backend.binary_name(), &args),
+            status,
+            stderr: captured.join("\n"),
+        })
+    }
+}
+
+/// Build several images concurrently, bounded by `concurrency`, collecting a
+/// `Result` per `spec` instead of aborting the whole batch the moment one
+/// fails - independent `podman build` invocations don't share any state, so
+/// there's nothing to serialize them for. `concurrency` is clamped to at
+/// least 1; `backend` must be `Sync` since it's shared across the pool's
+/// worker threads (true of every `Backend` this crate ships - `Podman`/
+/// `Docker`/`Nerdctl` are all stateless unit structs).
+pub fn build_images(
+    backend: &(dyn Backend + Sync),
+    specs: &[BuildSpec],
+    concurrency: usize,
+) -> Vec<Result<(), ExecError>> {
+    let build_all = || specs.par_iter().map(|spec| build_image(backend, spec)).collect();
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(concurrency.max(1))
+        .build()
+    {
+        Ok(pool) => pool.install(build_all),
+        // A dedicated pool failed to spin up (e.g. the host is out of
+        // threads) - fall back to running on rayon's global pool rather than
+        // losing the batch entirely.
+        Err(_) => build_all(),
     }
 }
 
-impl std::error::Error for ExecError {}
+pub fn image_exists(backend: &dyn Backend, image: &str) -> Result<bool, ExecError> {
+    let args = backend.build_image_exists_args(image);
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("image exists", backend.binary_name(), err))?;
 
-pub fn build_image(image: &str, context_dir: &Path) -> Result<(), ExecError> {
-    podman_cli::build_image(image, context_dir).map_err(|err| ExecError::new(err.to_string()))
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(ExecError::probe_failed(
+            "image exists",
+            backend.binary_name(),
+            &args,
+            &output,
+        )),
+    }
 }
 
-pub fn image_exists(image: &str) -> Result<bool, ExecError> {
-    podman_cli::image_exists(image).map_err(|err| ExecError::new(err.to_string()))
+/// Export `image` to a tar archive at `archive_path` (`<runtime> save -o`),
+/// so it can be restored elsewhere via `load_image` instead of rebuilt - a
+/// cache-transport path for CI cache restore, air-gapped transfer, or
+/// artifact upload.
+pub fn save_image(backend: &dyn Backend, image: &str, archive_path: &Path) -> Result<(), ExecError> {
+    let args = vec![
+        "save".to_string(),
+        "-o".to_string(),
+        archive_path.to_string_lossy().into_owned(),
+        image.to_string(),
+    ];
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("save", backend.binary_name(), err))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(ExecError::run_failed("save", backend.binary_name(), &args, &output))
+    }
+}
+
+/// Import an archive written by `save_image` (`<runtime> load -i`),
+/// returning the image reference the runtime reports having loaded - so a
+/// caller can hand it straight to `run_container`/`create` without assuming
+/// the archive wasn't re-tagged in transit.
+pub fn load_image(backend: &dyn Backend, archive_path: &Path) -> Result<String, ExecError> {
+    if !archive_path.is_file() {
+        return Err(ExecError::run_invalid(
+            "load",
+            format!(
+                "Error: image archive {} does not exist",
+                archive_path.display()
+            ),
+        ));
+    }
+
+    let args = vec![
+        "load".to_string(),
+        "-i".to_string(),
+        archive_path.to_string_lossy().into_owned(),
+    ];
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("load", backend.binary_name(), err))?;
+
+    if !output.status.success() {
+        return Err(ExecError::run_failed("load", backend.binary_name(), &args, &output));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_loaded_image(&stdout).ok_or_else(|| {
+        ExecError::run_invalid(
+            "load",
+            format!(
+                "Error: failed to parse loaded image reference from runtime output: {}",
+                stdout.trim()
+            ),
+        )
+    })
+}
+
+/// Pull the image reference out of `<runtime> load`'s stdout. Podman/Docker
+/// report `"Loaded image: <ref>"`; Podman can instead report `"Loaded image
+/// ID: <digest>"` when the archive carries no tag.
+fn parse_loaded_image(stdout: &str) -> Option<String> {
+    for line in stdout.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Loaded image:") {
+            return Some(rest.trim().to_string());
+        }
+        if let Some(rest) = line.strip_prefix("Loaded image ID:") {
+            return Some(rest.trim().to_string());
+        }
+    }
+    None
+}
+
+/// Image metadata parsed from `<runtime> inspect --format '{{json .}}'
+/// <image>`. Field names mirror the docker-compatible inspect JSON that
+/// podman/docker/nerdctl all emit (capitalized keys), the common subset
+/// this crate's `Backend`s target.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ImageInfo {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Created")]
+    pub created: String,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "Architecture")]
+    pub architecture: String,
+}
+
+/// Container metadata parsed the same way. `status`/`pid`/`exit_code` come
+/// from the nested `State` object the docker-compatible schema uses.
+/// `bundle` is populated only for runtimes that expose an OCI bundle path -
+/// podman/docker/nerdctl generally don't, that's a runc-specific concept -
+/// so it's `None` for the backends this crate ships today; it's kept as a
+/// field so a future runc-style backend can populate it without changing
+/// callers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ContainerState {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "State")]
+    pub state: ContainerStateInfo,
+    #[serde(default, rename = "Bundle")]
+    pub bundle: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ContainerStateInfo {
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "Pid")]
+    pub pid: i64,
+    #[serde(rename = "ExitCode")]
+    pub exit_code: i32,
+}
+
+pub fn inspect_image_info(backend: &dyn Backend, image: &str) -> Result<ImageInfo, ExecError> {
+    inspect_one(backend, image)
+}
+
+pub fn inspect_container(backend: &dyn Backend, id: &str) -> Result<ContainerState, ExecError> {
+    inspect_one(backend, id)
+}
+
+/// Shared `inspect --format '{{json .}}'` plumbing for
+/// `inspect_image_info`/`inspect_container`: capture stdout instead of just
+/// a status code, then parse it as typed JSON instead of string-scraping.
+fn inspect_one<T: DeserializeOwned>(backend: &dyn Backend, id: &str) -> Result<T, ExecError> {
+    let args = vec![
+        "inspect".to_string(),
+        "--format".to_string(),
+        "{{json .}}".to_string(),
+        id.to_string(),
+    ];
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("inspect", backend.binary_name(), err))?;
+
+    if !output.status.success() {
+        return Err(ExecError::probe_failed(
+            "inspect",
+            backend.binary_name(),
+            &args,
+            &output,
+        ));
+    }
+
+    parse_inspect_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// `{{json .}}` dumps the whole match set; inspecting a single id always
+/// yields exactly one element, but some runtimes still wrap it in an array -
+/// unwrap that before deserializing into the caller's shape.
+fn parse_inspect_json<T: DeserializeOwned>(text: &str) -> Result<T, ExecError> {
+    let value: serde_json::Value = serde_json::from_str(text.trim()).map_err(|err| {
+        ExecError::probe_invalid(
+            "inspect",
+            format!("Error: failed to parse runtime inspect output: {err}"),
+        )
+    })?;
+    let value = match value {
+        serde_json::Value::Array(mut items) if items.len() == 1 => items.remove(0),
+        other => other,
+    };
+    serde_json::from_value(value).map_err(|err| {
+        ExecError::probe_invalid(
+            "inspect",
+            format!("Error: failed to parse runtime inspect output: {err}"),
+        )
+    })
+}
+
+/// Id of a container created via `create`, as reported on stdout by the
+/// runtime CLI.
+pub type ContainerId = String;
+
+/// Prepare a container from `spec` without starting it (`<runtime> create`),
+/// returning its id. Lets a caller manage a long-lived background container
+/// through `start`/`stop`/`kill`/`delete` instead of only the one-shot
+/// foreground `run_container` flow.
+pub fn create(backend: &dyn Backend, spec: &ContainerSpec) -> Result<ContainerId, ExecError> {
+    let args = backend
+        .build_create_args(spec)
+        .map_err(|err| ExecError::run_invalid("create", err.to_string()))?;
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("create", backend.binary_name(), err))?;
+
+    if !output.status.success() {
+        return Err(ExecError::run_failed("create", backend.binary_name(), &args, &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-pub fn run_container(spec: &ContainerSpec) -> Result<(), ExecError> {
-    podman_cli::exec_run(spec).map_err(|err| ExecError::new(err.to_string()))
+/// Start a container previously prepared with `create`.
+pub fn start(backend: &dyn Backend, id: &str) -> Result<(), ExecError> {
+    run_verb(backend, "start", &[id.to_string()])
+}
+
+/// Stop a running container, asking the runtime to wait up to `timeout`
+/// before escalating to SIGKILL (the runtime's own default when omitted).
+pub fn stop(backend: &dyn Backend, id: &str, timeout: Option<Duration>) -> Result<(), ExecError> {
+    let mut args = vec!["stop".to_string()];
+    if let Some(timeout) = timeout {
+        args.push("-t".to_string());
+        args.push(timeout.as_secs().to_string());
+    }
+    args.push(id.to_string());
+    run_verb_args(backend, args)
+}
+
+/// Send `signal` (e.g. `"SIGTERM"`, `"SIGKILL"`) to a running container.
+pub fn kill(backend: &dyn Backend, id: &str, signal: &str) -> Result<(), ExecError> {
+    run_verb_args(
+        backend,
+        vec![
+            "kill".to_string(),
+            "-s".to_string(),
+            signal.to_string(),
+            id.to_string(),
+        ],
+    )
+}
+
+/// Remove a stopped container, or a running one when `force` is set.
+pub fn delete(backend: &dyn Backend, id: &str, force: bool) -> Result<(), ExecError> {
+    let mut args = vec!["rm".to_string()];
+    if force {
+        args.push("-f".to_string());
+    }
+    args.push(id.to_string());
+    run_verb_args(backend, args)
+}
+
+/// Run a command inside an already-running container (`<runtime> exec`),
+/// passing `env` as `-e KEY=VALUE` pairs, and return its captured output.
+pub fn exec_in_container(
+    backend: &dyn Backend,
+    id: &str,
+    argv: &[String],
+    env: &std::collections::BTreeMap<String, String>,
+) -> Result<std::process::Output, ExecError> {
+    let mut args = vec!["exec".to_string()];
+    for (key, value) in env {
+        args.push("-e".to_string());
+        args.push(format!("{key}={value}"));
+    }
+    args.push(id.to_string());
+    args.extend(argv.iter().cloned());
+
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found("exec", backend.binary_name(), err))?;
+    if !output.status.success() {
+        return Err(ExecError::run_failed("exec", backend.binary_name(), &args, &output));
+    }
+    Ok(output)
+}
+
+/// Stream a container's logs (`<runtime> logs`, optionally `-f` to follow)
+/// as a `Read` over its stdout. The spawned `Child` is kept alongside the
+/// reader for as long as it's alive - dropping it would close the pipe
+/// mid-stream, and with `follow: true` there's no EOF to wait for instead.
+pub struct ContainerLogs {
+    child: std::process::Child,
+}
+
+impl std::io::Read for ContainerLogs {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.child
+            .stdout
+            .as_mut()
+            .expect("logs spawned with a piped stdout")
+            .read(buf)
+    }
+}
+
+pub fn logs(backend: &dyn Backend, id: &str, follow: bool) -> Result<ContainerLogs, ExecError> {
+    let mut args = vec!["logs".to_string()];
+    if follow {
+        args.push("-f".to_string());
+    }
+    args.push(id.to_string());
+
+    let child = Command::new(backend.binary_name())
+        .args(&args)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| ExecError::not_found("logs", backend.binary_name(), err))?;
+    Ok(ContainerLogs { child })
+}
+
+/// Shared plumbing for the simple lifecycle verbs (`start`/`stop`/`kill`/
+/// `delete`) that take no output beyond success/failure.
+fn run_verb(backend: &dyn Backend, verb: &str, rest: &[String]) -> Result<(), ExecError> {
+    let mut args = vec![verb.to_string()];
+    args.extend(rest.iter().cloned());
+    run_verb_args(backend, args)
+}
+
+fn run_verb_args(backend: &dyn Backend, args: Vec<String>) -> Result<(), ExecError> {
+    let verb = args[0].clone();
+    let output = Command::new(backend.binary_name())
+        .args(&args)
+        .output()
+        .map_err(|err| ExecError::not_found(&verb, backend.binary_name(), err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(ExecError::run_failed(&verb, backend.binary_name(), &args, &output))
+    }
+}
+
+/// Run the container, retrying with exponential backoff (starting at
+/// `RUN_RETRY_INITIAL_BACKOFF`, doubling up to `retry_backoff_cap`) when the
+/// runtime itself reports exit code 125 - the podman/docker/nerdctl
+/// convention for a transient failure in the runtime command (a stale
+/// cgroup path, a "layer already being used" race, ...) rather than the
+/// container's own command failing. Any other exit code terminates this
+/// process immediately with that same code, mirroring how `exec` used to
+/// make this process *become* the container run.
+///
+/// `owned_jobserver`, if set, is dropped - cleaning up its fifo and
+/// directory - right before that terminating exit, since `std::process::exit`
+/// skips destructors; the retry-exhausted error path below still runs it
+/// through ordinary drop glue on return.
+#[cfg(unix)]
+pub fn run_container(
+    backend: &dyn Backend,
+    spec: &ContainerSpec,
+    retry_backoff_cap: Option<Duration>,
+    owned_jobserver: Option<OwnedJobserver>,
+) -> Result<(), ExecError> {
+    let args = backend
+        .build_run_args(spec)
+        .map_err(|err| ExecError::run_invalid("run", err.to_string()))?;
+
+    let mut backoff = RUN_RETRY_INITIAL_BACKOFF;
+    for attempt in 1..=RUN_RETRY_ATTEMPTS {
+        let status = Command::new(backend.binary_name())
+            .args(&args)
+            .status()
+            .map_err(|err| ExecError::not_found("run", backend.binary_name(), err))?;
+
+        if !is_runtime_failure(status.code()) {
+            drop(owned_jobserver);
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        if attempt == RUN_RETRY_ATTEMPTS {
+            return Err(ExecError::RunFailed {
+                action: "run".to_string(),
+                command: full_command(backend.binary_name(), &args),
+                status: Some(status),
+                stderr: format!(
+                    "runtime run kept failing (exit {}) after {RUN_RETRY_ATTEMPTS} attempts",
+                    RUNTIME_FAILURE_EXIT_CODE
+                ),
+            });
+        }
+
+        std::thread::sleep(backoff);
+        backoff = next_backoff(backoff, retry_backoff_cap);
+    }
+
+    unreachable!("loop above always exits or returns")
+}
+
+#[cfg(not(unix))]
+pub fn run_container(
+    _backend: &dyn Backend,
+    _spec: &ContainerSpec,
+    _retry_backoff_cap: Option<Duration>,
+    _owned_jobserver: Option<OwnedJobserver>,
+) -> Result<(), ExecError> {
+    Err(ExecError::run_invalid(
+        "run",
+        "Error: runtime exec is only supported on unix platforms",
+    ))
+}
+
+/// Async mirrors of the build/exists/run surface above, built on
+/// `tokio::process::Command` instead of the blocking `std::process::Command`
+/// so callers can launch and await several builds/runs concurrently -
+/// rust-runc offers the same kind of async wrapper over its runtime CLI.
+/// `Backend::build_build_args`/`build_run_args` are reused unchanged, so the
+/// argv logic stays shared between the sync and async paths; only the
+/// process spawn differs.
+#[cfg(feature = "async")]
+pub mod async_exec {
+    use super::{ContainerSpec, ExecError};
+    use crate::internal::BuildSpec;
+    use crate::runtime::Backend;
+    use tokio::process::Command;
+
+    pub async fn build_image(backend: &dyn Backend, spec: &BuildSpec) -> Result<(), ExecError> {
+        let args = backend.build_build_args(spec);
+        let output = Command::new(backend.binary_name())
+            .args(&args)
+            .output()
+            .await
+            .map_err(|err| ExecError::not_found("build", backend.binary_name(), err))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(ExecError::build_failed(backend.binary_name(), &args, &output))
+        }
+    }
+
+    pub async fn image_exists(backend: &dyn Backend, image: &str) -> Result<bool, ExecError> {
+        let args = backend.build_image_exists_args(image);
+        let output = Command::new(backend.binary_name())
+            .args(&args)
+            .output()
+            .await
+            .map_err(|err| ExecError::not_found("image exists", backend.binary_name(), err))?;
+
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(ExecError::probe_failed(
+                "image exists",
+                backend.binary_name(),
+                &args,
+                &output,
+            )),
+        }
+    }
+
+    /// Non-replacing async run: unlike `run_container`'s interactive
+    /// fast path (which inherits stdio and exits this process to become the
+    /// container run), this captures the container's output and hands it
+    /// back to the caller, so several containers can be launched and
+    /// awaited side by side without tearing down the host process.
+    pub async fn run_captured(
+        backend: &dyn Backend,
+        spec: &ContainerSpec,
+    ) -> Result<std::process::Output, ExecError> {
+        let args = backend
+            .build_run_args(spec)
+            .map_err(|err| ExecError::run_invalid("run", err.to_string()))?;
+        Command::new(backend.binary_name())
+            .args(&args)
+            .output()
+            .await
+            .map_err(|err| ExecError::not_found("run", backend.binary_name(), err))
+    }
+}
+
+/// Whether an exit code is the podman/docker/nerdctl convention for "the
+/// runtime command itself failed", which is worth retrying (as opposed to
+/// the container's own command exit status, which should propagate as-is).
+fn is_runtime_failure(code: Option<i32>) -> bool {
+    code == Some(RUNTIME_FAILURE_EXIT_CODE)
+}
+
+/// Double `backoff`, capped at `max_backoff` (`None` behaves as effectively
+/// unbounded).
+fn next_backoff(backoff: Duration, max_backoff: Option<Duration>) -> Duration {
+    let doubled = backoff.saturating_mul(2);
+    match max_backoff {
+        Some(cap) => doubled.min(cap),
+        None => doubled,
+    }
+}
+
+fn format_exit_status(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => code.to_string(),
+        None => "signal".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        context_digest, is_runtime_failure, load_image, next_backoff, parse_inspect_json,
+        parse_loaded_image, ContainerState, ExecError, ImageInfo, RUNTIME_FAILURE_EXIT_CODE,
+    };
+    use crate::internal::BuildSpec;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_loaded_image_reads_loaded_image_line() {
+        assert_eq!(
+            parse_loaded_image("Loaded image: registry.local/app:latest\n"),
+            Some("registry.local/app:latest".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_loaded_image_reads_loaded_image_id_line() {
+        assert_eq!(
+            parse_loaded_image("Loaded image ID: sha256:deadbeef\n"),
+            Some("sha256:deadbeef".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_loaded_image_rejects_unrecognized_output() {
+        assert_eq!(parse_loaded_image("nothing useful here\n"), None);
+    }
+
+    #[test]
+    fn load_image_rejects_missing_archive() {
+        use crate::runtime::Podman;
+        let err = load_image(&Podman, std::path::Path::new("/nonexistent/archive.tar"))
+            .err()
+            .expect("expected load_image to fail");
+        assert!(
+            err.to_string().contains("does not exist"),
+            "unexpected error message: {err}"
+        );
+    }
+
+    #[test]
+    fn is_runtime_failure_matches_only_exit_125() {
+        assert!(is_runtime_failure(Some(RUNTIME_FAILURE_EXIT_CODE)));
+        assert!(!is_runtime_failure(Some(0)));
+        assert!(!is_runtime_failure(Some(1)));
+        assert!(!is_runtime_failure(None));
+    }
+
+    #[test]
+    fn next_backoff_doubles_uncapped() {
+        let backoff = next_backoff(Duration::from_millis(10), None);
+        assert_eq!(backoff, Duration::from_millis(20));
+        let backoff = next_backoff(backoff, None);
+        assert_eq!(backoff, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn next_backoff_respects_cap() {
+        let cap = Some(Duration::from_millis(25));
+        let backoff = next_backoff(Duration::from_millis(10), cap);
+        assert_eq!(backoff, Duration::from_millis(20));
+        let backoff = next_backoff(backoff, cap);
+        assert_eq!(backoff, Duration::from_millis(25));
+    }
+
+    #[test]
+    fn parse_inspect_json_reads_image_fields() {
+        let info: ImageInfo = parse_inspect_json(
+            r#"{"Id":"sha256:abc","Created":"2024-01-01T00:00:00Z","Size":1234,"Architecture":"amd64"}"#,
+        )
+        .expect("parse_inspect_json failed");
+        assert_eq!(info.id, "sha256:abc");
+        assert_eq!(info.size, 1234);
+        assert_eq!(info.architecture, "amd64");
+    }
+
+    #[test]
+    fn parse_inspect_json_unwraps_single_element_array() {
+        let info: ImageInfo = parse_inspect_json(
+            r#"[{"Id":"sha256:abc","Created":"2024-01-01T00:00:00Z","Size":1234,"Architecture":"amd64"}]"#,
+        )
+        .expect("parse_inspect_json failed");
+        assert_eq!(info.id, "sha256:abc");
+    }
+
+    #[test]
+    fn parse_inspect_json_reads_nested_container_state() {
+        let state: ContainerState = parse_inspect_json(
+            r#"{"Id":"container1","State":{"Status":"running","Pid":4242,"ExitCode":0}}"#,
+        )
+        .expect("parse_inspect_json failed");
+        assert_eq!(state.id, "container1");
+        assert_eq!(state.state.status, "running");
+        assert_eq!(state.state.pid, 4242);
+        assert_eq!(state.state.exit_code, 0);
+        assert!(state.bundle.is_none());
+    }
+
+    #[test]
+    fn build_failed_renders_stderr_and_command_in_display() {
+        let output = Output {
+            status: ExitStatus::from_raw(125 << 8),
+            stdout: Vec::new(),
+            stderr: b"Error: layer already being used by container\n".to_vec(),
+        };
+        let err = ExecError::build_failed(
+            "podman",
+            &["build".to_string(), "-t".to_string(), "app".to_string()],
+            &output,
+        );
+        assert_eq!(
+            err.to_string(),
+            "Error: runtime build failed (exit 125): Error: layer already being used by container (command: podman build -t app)"
+        );
+    }
+
+    #[test]
+    fn probe_failed_omits_empty_stderr() {
+        let output = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        let err = ExecError::probe_failed("image exists", "docker", &["image".to_string()], &output);
+        assert_eq!(
+            err.to_string(),
+            "Error: runtime image exists failed (exit 1) (command: docker image)"
+        );
+    }
+
+    #[test]
+    fn runtime_not_found_chains_source() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "No such file or directory");
+        let err = ExecError::not_found("run", "podman", io_err);
+        assert!(err.to_string().starts_with("Error: failed to launch runtime run (podman):"));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn context_digest_changes_when_a_context_file_changes() {
+        let root = TempDir::new().expect("tempdir");
+        fs::write(root.path().join("Containerfile"), "FROM scratch").unwrap();
+        let spec = BuildSpec {
+            image: "app".to_string(),
+            context_dir: root.path().to_path_buf(),
+            dockerfile: None,
+            build_args: BTreeMap::new(),
+        };
+        let first = context_digest(&spec).expect("context_digest");
+
+        fs::write(root.path().join("Containerfile"), "FROM scratch2").unwrap();
+        let second = context_digest(&spec).expect("context_digest");
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn context_digest_changes_when_build_args_change() {
+        let root = TempDir::new().expect("tempdir");
+        fs::write(root.path().join("Containerfile"), "FROM scratch").unwrap();
+        let mut spec = BuildSpec {
+            image: "app".to_string(),
+            context_dir: root.path().to_path_buf(),
+            dockerfile: None,
+            build_args: BTreeMap::new(),
+        };
+        let first = context_digest(&spec).expect("context_digest");
+
+        spec.build_args.insert("VERSION".to_string(), "1".to_string());
+        let second = context_digest(&spec).expect("context_digest");
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn parse_inspect_json_errors_on_invalid_json() {
+        let err = parse_inspect_json::<ImageInfo>("not json")
+            .err()
+            .expect("expected parse_inspect_json to fail");
+        assert!(
+            err.to_string()
+                .starts_with("Error: failed to parse runtime inspect output:"),
+            "unexpected error message: {err}"
+        );
+    }
 }
@@ -16,7 +16,26 @@ pub struct ContainerSpec {
     pub workdir: Option<PathBuf>,
     pub user: Option<String>,
     pub extra_hosts: Vec<String>,
+    /// `--userns` value, e.g. `keep-id:uid=1000,gid=1000` for rootless mode.
+    pub userns: Option<String>,
+    /// Run with every capability via `--privileged`, bypassing `cap_add`/
+    /// `cap_drop` entirely. An explicit escape hatch - `false` is the
+    /// default and routes through the fine-grained capability model below
+    /// instead.
     pub privileged: bool,
+    /// Capabilities to strip via `--cap-drop` when not `privileged` (e.g.
+    /// `"ALL"`).
+    pub cap_drop: Vec<String>,
+    /// Capabilities to re-grant via `--cap-add` on top of `cap_drop` when
+    /// not `privileged` (e.g. `"CHOWN"`, `"SETUID"`).
+    pub cap_add: Vec<String>,
+    /// Emit `--security-opt no-new-privileges`. Opt-in and independent of
+    /// `cap_drop`/`cap_add`: this bit is inherited by every process in the
+    /// container, including a setuid `sudo` - so turning it on by default
+    /// alongside the default cap model would silently neuter the default
+    /// `PrivilegeBackend::Sudoers` grant the same way an unconditional
+    /// `PR_SET_NO_NEW_PRIVS` would (see `InternalSpec::no_new_privs`).
+    pub no_new_privileges: bool,
     pub init: bool,
     pub remove: bool,
     pub interactive: bool,
@@ -24,15 +43,104 @@ pub struct ContainerSpec {
     pub entrypoint: Option<Vec<String>>,
     pub command: Vec<String>,
     pub extra_args: Vec<String>,
+    /// Extra fds (beyond stdio) the runtime should keep open and pass
+    /// through to the container, for forwarding a legacy fd-style `make`
+    /// jobserver. `0` means nothing to preserve.
+    pub preserve_fds: u32,
+    /// `--memory` value, e.g. `"2g"`, from the `mem_limit` config param.
+    pub mem_limit: Option<String>,
+    /// `--cpus` value, e.g. `"2.5"`, from the `cpu_limit` config param.
+    pub cpu_limit: Option<String>,
+    /// `--pids-limit` value from the `pids_limit` config param.
+    pub pids_limit: Option<String>,
 }
 
-/// Bind mount definition for the container.
+/// Host-only inputs to a `runtime build` invocation. Unlike `ContainerSpec`
+/// this never crosses the host/agent boundary, so it doesn't need to
+/// serialize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildSpec {
+    pub image: String,
+    pub context_dir: PathBuf,
+    /// Rendered Containerfile/Dockerfile path (`-f`), set when a
+    /// `containerfile_template` is configured.
+    pub dockerfile: Option<PathBuf>,
+    /// `--build-arg` key/value pairs from the `build_args` config param.
+    pub build_args: BTreeMap<String, String>,
+}
+
+/// A filesystem attachment for the container: a host bind mount, an
+/// ephemeral `tmpfs`, or a runtime-managed named volume. Modeled after how
+/// OCI-runtime tooling (e.g. youki) distinguishes mount kinds at the spec
+/// level, rather than flattening everything into `-v source:target:opts`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Mount {
-    pub source: PathBuf,
-    pub target: PathBuf,
-    pub read_only: bool,
-    pub options: Vec<String>,
+pub enum Mount {
+    Bind {
+        source: PathBuf,
+        target: PathBuf,
+        read_only: bool,
+        /// Bind propagation (`--mount`/`-v` `rprivate`/`rshared`/`rslave`
+        /// flag), unset to let the runtime default apply.
+        propagation: Option<MountPropagation>,
+        /// SELinux relabel flag (`z` shared, `Z` private), unset to skip
+        /// relabeling.
+        selinux_relabel: Option<SelinuxRelabel>,
+        /// Any other raw `-v` options (e.g. `nocopy`), passed through as-is.
+        options: Vec<String>,
+    },
+    Tmpfs {
+        target: PathBuf,
+        /// `size=` value, e.g. `"100m"`.
+        size: Option<String>,
+        /// `mode=` value, e.g. `"1777"`.
+        mode: Option<String>,
+    },
+    Volume {
+        /// Name of a runtime-managed named volume (created if missing).
+        name: String,
+        target: PathBuf,
+        read_only: bool,
+    },
+}
+
+/// Bind mount propagation mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MountPropagation {
+    RPrivate,
+    RShared,
+    RSlave,
+}
+
+impl MountPropagation {
+    /// The flag this propagation mode is spelled as in `-v`/`--mount` option
+    /// lists.
+    pub fn as_flag(self) -> &'static str {
+        match self {
+            MountPropagation::RPrivate => "rprivate",
+            MountPropagation::RShared => "rshared",
+            MountPropagation::RSlave => "rslave",
+        }
+    }
+}
+
+/// SELinux relabel flag for a bind mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelinuxRelabel {
+    /// `z` - label so the mount is shared across containers.
+    Shared,
+    /// `Z` - label so the mount is private to this container.
+    Private,
+}
+
+impl SelinuxRelabel {
+    /// The flag this relabel mode is spelled as in `-v`/`--mount` option
+    /// lists.
+    pub fn as_flag(self) -> &'static str {
+        match self {
+            SelinuxRelabel::Shared => "z",
+            SelinuxRelabel::Private => "Z",
+        }
+    }
 }
 
 /// Spec passed to the in-container agent.
@@ -50,6 +158,65 @@ pub struct InternalSpec {
     pub extra_shell: Option<PathBuf>,
     pub prefix_cmd: Vec<String>,
     pub prefix_cmd_quiet: Vec<String>,
+    /// Allocate a PTY for the spawned shell instead of inheriting stdio
+    /// directly, so interactive programs get a real controlling terminal.
+    pub pty: bool,
+    /// How the agent grants the container user elevated privileges.
+    pub privilege_backend: PrivilegeBackend,
+    /// Set when the container was launched rootless (`--userns=keep-id`),
+    /// meaning the entrypoint already runs as the target uid/gid instead of
+    /// root. The agent skips user provisioning and the setuid drop in this
+    /// mode since there is no root to create the account or step down from.
+    pub rootless: bool,
+    /// Set `PR_SET_NO_NEW_PRIVS` immediately before exec'ing the shell. Once
+    /// set this bit is inherited across `execve` and can never be cleared,
+    /// so no descendant of the shell - including via the `sudo` entry
+    /// `setup_user` writes - can gain privileges through setuid/setgid binaries or
+    /// file capabilities. Opt-in since it also blocks legitimate uses of
+    /// sudo inside the sandbox.
+    pub no_new_privs: bool,
+    /// Restrict the sudoers entry `setup_user` writes to a specific command
+    /// allowlist instead of the default blanket `ALL=(ALL) NOPASSWD: ALL`.
+    pub sudo_policy: Option<SudoPolicy>,
+    /// Restrict which inherited environment variables reach the shell.
+    /// `None` inherits everything, matching pre-filter behavior.
+    pub env_filter: Option<EnvFilter>,
+}
+
+/// An allowlist/denylist pair for sanitizing inherited environment
+/// variables, applied in `build_base_env` before `env_overrides` are
+/// layered on top. Modeled on the `inherit_envs` allowlist in `raou`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct EnvFilter {
+    /// Extra variable names to keep, beyond the built-in safe defaults
+    /// (`HOME`, `TERM`, `LANG`, ...).
+    pub allow: Vec<String>,
+    /// Variable names to always strip, even if otherwise allowed - wins
+    /// over both `allow` and the built-in defaults (e.g. `LD_PRELOAD`).
+    pub deny: Vec<String>,
+}
+
+/// An allowlist-scoped sudo grant, written as a single sudoers line. Modeled
+/// on the per-entry command/argv policy in the `raou`/`quinoa` tools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SudoPolicy {
+    /// The `(runas)` user sudo may run commands as, e.g. `"root"` or `"ALL"`.
+    pub runas: String,
+    /// Absolute paths of the only commands the grant covers.
+    pub commands: Vec<String>,
+    /// When `false`, each command is pinned to being invoked with no
+    /// arguments (sudoers `cmd ""`); when `true`, any arguments are allowed.
+    pub arbitrary_args: bool,
+}
+
+/// Backend used by the agent to grant the container user sudo-equivalent
+/// privileges. `Sudoers` is the default for backward compatibility;
+/// `Pam` scopes the grant to the session instead of editing a system file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum PrivilegeBackend {
+    #[default]
+    Sudoers,
+    Pam,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -58,6 +225,13 @@ pub struct UserSpec {
     pub uid: u32,
     pub gid: u32,
     pub home: PathBuf,
+    /// Supplementary group ids to install before dropping privileges. Empty
+    /// explicitly clears the inherited (root) group set.
+    pub groups: Vec<u32>,
+    /// Set when `home` is backed by a persistent host volume (`home_volume`)
+    /// bind-mounted into the container, rather than ephemeral per-container
+    /// storage that disappears when the `--rm` container exits.
+    pub persistent_home: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -0,0 +1,351 @@
+//! GNU make jobserver detection, forwarding, and creation across the
+//! container boundary. Nested `make` invocations inside the container need
+//! to see a jobserver (advertised via `MAKEFLAGS`) so they respect a job
+//! budget instead of oversubscribing CPUs.
+//!
+//! Two independent ways to get one in: `prepare_forward` forwards the
+//! *parent* `make -jN`'s existing jobserver (gated behind the
+//! `forward_jobserver` config param), while `create_owned` mints a brand
+//! new one owned by giftwrap itself (gated behind `own_jobserver`/
+//! `job_slots`) for runs that aren't themselves under `make -jN` but still
+//! want sub-builds inside the container capped to a job budget.
+
+use std::ffi::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+
+use crate::internal::Mount;
+
+#[derive(Debug)]
+pub struct JobserverError {
+    message: String,
+}
+
+impl JobserverError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for JobserverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for JobserverError {}
+
+/// What `MAKEFLAGS` advertised for the jobserver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JobserverAuth {
+    /// `--jobserver-auth=fifo:PATH` (GNU make >= 4.4).
+    Fifo(PathBuf),
+    /// `--jobserver-auth=R,W`, or the legacy `--jobserver-fds=R,W` spelling.
+    Fds(c_int, c_int),
+}
+
+/// Fixed fd numbers the legacy fd-style jobserver is renumbered to before
+/// exec'ing into the container runtime, matching the `preserve_fds` count
+/// passed on `ContainerSpec` so the runtime knows to keep them open.
+const SHIM_READ_FD: c_int = 3;
+const SHIM_WRITE_FD: c_int = 4;
+
+/// Result of preparing a jobserver forward: the (possibly rewritten)
+/// `MAKEFLAGS` value to export into the container, an extra mount needed
+/// for the fifo form, and how many trailing fds (beyond stdio) the runtime
+/// needs to preserve for the fd form.
+pub struct JobserverForward {
+    pub makeflags: String,
+    pub mount: Option<Mount>,
+    pub preserve_fds: u32,
+}
+
+/// Detect a jobserver from the current process's `MAKEFLAGS` and prepare it
+/// to cross the container boundary. Returns `Ok(None)` when there is
+/// nothing to forward (not running under `make -jN`, or no recognizable
+/// jobserver flag in `MAKEFLAGS`).
+pub fn prepare_forward() -> Result<Option<JobserverForward>, JobserverError> {
+    let Ok(makeflags) = std::env::var("MAKEFLAGS") else {
+        return Ok(None);
+    };
+    let Some(auth) = parse_auth(&makeflags) else {
+        return Ok(None);
+    };
+
+    match auth {
+        JobserverAuth::Fifo(path) => Ok(Some(JobserverForward {
+            makeflags,
+            mount: Some(Mount::Bind {
+                source: path.clone(),
+                target: path,
+                read_only: false,
+                propagation: None,
+                selinux_relabel: None,
+                options: Vec::new(),
+            }),
+            preserve_fds: 0,
+        })),
+        JobserverAuth::Fds(read_fd, write_fd) => {
+            dup_and_clear_cloexec(read_fd, SHIM_READ_FD)?;
+            dup_and_clear_cloexec(write_fd, SHIM_WRITE_FD)?;
+            Ok(Some(JobserverForward {
+                makeflags: rewrite_fd_makeflags(&makeflags),
+                mount: None,
+                preserve_fds: 2,
+            }))
+        }
+    }
+}
+
+fn parse_auth(makeflags: &str) -> Option<JobserverAuth> {
+    makeflags.split_whitespace().find_map(|token| {
+        token
+            .strip_prefix("--jobserver-auth=")
+            .or_else(|| token.strip_prefix("--jobserver-fds="))
+            .and_then(parse_auth_value)
+    })
+}
+
+fn parse_auth_value(value: &str) -> Option<JobserverAuth> {
+    if let Some(path) = value.strip_prefix("fifo:") {
+        return Some(JobserverAuth::Fifo(PathBuf::from(path)));
+    }
+    let (read_fd, write_fd) = value.split_once(',')?;
+    Some(JobserverAuth::Fds(
+        read_fd.parse().ok()?,
+        write_fd.parse().ok()?,
+    ))
+}
+
+/// Replace the `--jobserver-auth=`/`--jobserver-fds=` token with one
+/// pointing at the renumbered shim fds, leaving the rest of `MAKEFLAGS`
+/// untouched.
+fn rewrite_fd_makeflags(makeflags: &str) -> String {
+    let replacement = format!("--jobserver-auth={SHIM_READ_FD},{SHIM_WRITE_FD}");
+    makeflags
+        .split_whitespace()
+        .map(|token| {
+            if token.starts_with("--jobserver-auth=") || token.starts_with("--jobserver-fds=") {
+                replacement.as_str()
+            } else {
+                token
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `dup2` `fd` onto `target`, then clear `FD_CLOEXEC` on `target` so it
+/// survives the `exec` into the container runtime binary.
+fn dup_and_clear_cloexec(fd: c_int, target: c_int) -> Result<(), JobserverError> {
+    unsafe {
+        if libc::dup2(fd, target) < 0 {
+            return Err(JobserverError::new(format!(
+                "Error: failed to dup jobserver fd {fd} onto {target}: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let flags = libc::fcntl(target, libc::F_GETFD);
+        if flags < 0 || libc::fcntl(target, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+            return Err(JobserverError::new(format!(
+                "Error: failed to clear FD_CLOEXEC on jobserver fd {target}: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A POSIX-fifo-backed jobserver giftwrap mints and owns itself, as opposed
+/// to `prepare_forward`'s forward of an already-running one. Pre-loaded
+/// with `job_slots - 1` tokens, matching GNU make's own convention that the
+/// jobserver holds `N - 1` tokens since the main job occupies the Nth slot.
+pub struct OwnedJobserver {
+    dir: PathBuf,
+    fifo_path: PathBuf,
+    /// Held open for the jobserver's whole lifetime so the fifo always has
+    /// at least one writer, independent of whether/when the container's
+    /// make processes have their own ends open.
+    write_fd: RawFd,
+}
+
+impl OwnedJobserver {
+    /// The `MAKEFLAGS` value to export into the container for `job_slots`
+    /// concurrent jobs.
+    pub fn makeflags(&self, job_slots: u32) -> String {
+        format!(
+            "--jobserver-auth=fifo:{} -j{job_slots}",
+            self.fifo_path.display()
+        )
+    }
+
+    /// Bind mount exposing the fifo at the same path inside the container.
+    pub fn mount(&self) -> Mount {
+        Mount::Bind {
+            source: self.fifo_path.clone(),
+            target: self.fifo_path.clone(),
+            read_only: false,
+            propagation: None,
+            selinux_relabel: None,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl Drop for OwnedJobserver {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.write_fd);
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Host CPU count `job_slots` defaults to when the config doesn't set one
+/// explicitly.
+pub fn default_job_slots() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1)
+}
+
+/// Create a fresh fifo-backed jobserver pre-loaded with `job_slots - 1`
+/// tokens under a giftwrap-managed temp directory, independent of any
+/// parent `make` jobserver.
+pub fn create_owned(job_slots: u32) -> Result<OwnedJobserver, JobserverError> {
+    let dir = std::env::temp_dir().join(format!("giftwrap-jobserver-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|err| {
+        JobserverError::new(format!(
+            "Error: failed to create jobserver directory {}: {err}",
+            dir.display()
+        ))
+    })?;
+    let fifo_path = dir.join("jobserver.fifo");
+    let c_path = std::ffi::CString::new(fifo_path.as_os_str().as_bytes())
+        .map_err(|_| JobserverError::new("Error: jobserver path contains a NUL byte"))?;
+
+    unsafe {
+        if libc::mkfifo(c_path.as_ptr(), 0o600) != 0 {
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(JobserverError::new(format!(
+                "Error: failed to create jobserver fifo: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    // Open read-write rather than write-only so this open doesn't block
+    // waiting for a reader to show up - nothing will read until the
+    // container starts. The fd stays open for the jobserver's lifetime so
+    // there is always at least one writer, even if every in-container
+    // client momentarily closes its own end.
+    let write_fd = unsafe { libc::open(c_path.as_ptr(), libc::O_RDWR) };
+    if write_fd < 0 {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(JobserverError::new(format!(
+            "Error: failed to open jobserver fifo: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+
+    let token = [b'+'];
+    for _ in 0..job_slots.saturating_sub(1) {
+        let written = unsafe { libc::write(write_fd, token.as_ptr() as *const libc::c_void, 1) };
+        if written != 1 {
+            unsafe {
+                libc::close(write_fd);
+            }
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(JobserverError::new(format!(
+                "Error: failed to preload jobserver token: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    Ok(OwnedJobserver {
+        dir,
+        fifo_path,
+        write_fd,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_owned, default_job_slots, parse_auth, rewrite_fd_makeflags, JobserverAuth};
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_auth_recognizes_fifo_form() {
+        let auth = parse_auth("-j --jobserver-auth=fifo:/tmp/gnumake-fifo.abc --").unwrap();
+        assert_eq!(
+            auth,
+            JobserverAuth::Fifo(PathBuf::from("/tmp/gnumake-fifo.abc"))
+        );
+    }
+
+    #[test]
+    fn parse_auth_recognizes_fd_form() {
+        let auth = parse_auth("-j --jobserver-auth=6,7").unwrap();
+        assert_eq!(auth, JobserverAuth::Fds(6, 7));
+    }
+
+    #[test]
+    fn parse_auth_recognizes_legacy_jobserver_fds() {
+        let auth = parse_auth("-j --jobserver-fds=6,7 -j").unwrap();
+        assert_eq!(auth, JobserverAuth::Fds(6, 7));
+    }
+
+    #[test]
+    fn parse_auth_returns_none_without_jobserver_flag() {
+        assert!(parse_auth("-j4").is_none());
+        assert!(parse_auth("").is_none());
+    }
+
+    #[test]
+    fn rewrite_fd_makeflags_renumbers_only_the_jobserver_token() {
+        let rewritten = rewrite_fd_makeflags("-j --jobserver-auth=6,7 -- extra");
+        assert_eq!(rewritten, "-j --jobserver-auth=3,4 -- extra");
+    }
+
+    #[test]
+    fn create_owned_preloads_job_slots_minus_one_tokens() {
+        let jobserver = create_owned(4).expect("create_owned");
+        unsafe {
+            libc::fcntl(jobserver.write_fd, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+        let mut buf = [0u8; 8];
+        let mut total = 0usize;
+        loop {
+            let n = unsafe {
+                libc::read(
+                    jobserver.write_fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            total += n as usize;
+        }
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn owned_jobserver_makeflags_points_at_the_fifo() {
+        let jobserver = create_owned(2).expect("create_owned");
+        let flags = jobserver.makeflags(2);
+        assert!(flags.starts_with("--jobserver-auth=fifo:"));
+        assert!(flags.contains(&jobserver.fifo_path.display().to_string()));
+        assert!(flags.ends_with(" -j2"));
+    }
+
+    #[test]
+    fn default_job_slots_is_at_least_one() {
+        assert!(default_job_slots() >= 1);
+    }
+}
@@ -4,9 +4,15 @@ mod config;
 mod context;
 mod exec;
 mod internal;
-mod podman_cli;
-
-use std::ffi::CStr;
+mod jobserver;
+mod pty;
+mod runtime;
+mod staleness;
+mod template;
+#[cfg(feature = "test-support")]
+mod testsupport;
+
+use std::ffi::{CStr, CString};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -29,9 +35,25 @@ fn run() -> Result<(), String> {
 
     let orig_cwd =
         env::current_dir().map_err(|err| format!("Error: failed to resolve cwd: {err}"))?;
+
+    // Aliases live in config, but which config to load depends on CLI
+    // options (--gw-config-file=, --gw-config=) that only come out of
+    // parse_args - so resolve them in a best-effort bootstrap pass first,
+    // using default discovery and tolerating failure (most invocations
+    // don't define any alias_* params, and a config problem here is
+    // reported properly once the real load below runs anyway).
+    let aliases = config::load_from_with_overrides(&orig_cwd, None, &[])
+        .map(|config| alias_table(&config.params))
+        .unwrap_or_default();
+    let args = cli::expand_aliases(&args, &aliases).map_err(|err| err.to_string())?;
     let (cli_opts, user_cmd) = cli::parse_args(&args).map_err(|err| err.to_string())?;
 
-    let config = config::load_from(&orig_cwd).map_err(|err| err.to_string())?;
+    let config = config::load_from_with_overrides(
+        &orig_cwd,
+        cli_opts.config_file.as_deref().map(Path::new),
+        &cli_opts.config_overrides,
+    )
+    .map_err(|err| err.to_string())?;
     let root_dir = config.root_dir.clone();
     env::set_current_dir(&root_dir)
         .map_err(|err| format!("Error: failed to enter build root: {err}"))?;
@@ -41,6 +63,8 @@ fn run() -> Result<(), String> {
         .entry("extra_args".to_string())
         .or_insert_with(Vec::new);
 
+    let backend = select_runtime(&params, cli_opts.runtime_override.as_deref())?;
+
     let context = context::load_from_config(&root_dir, &params).map_err(|err| err.to_string())?;
     let mut ctx_sha = context.as_ref().map(|ctx| ctx.sha.clone());
     if let Some(forced) = &cli_opts.use_ctx {
@@ -83,9 +107,35 @@ fn run() -> Result<(), String> {
         run_hook(hook, &root_dir)?;
     }
 
-    if let Some(rebuild_image) = rebuild_plan(cli_opts.rebuild, &image) {
-        println!("Rebuilding container {rebuild_image}");
-        exec::build_image(&rebuild_image, &root_dir).map_err(|err| err.to_string())?;
+    let uid = unsafe { libc::getuid() } as u32;
+    let gid = unsafe { libc::getgid() } as u32;
+
+    let mut rebuild_plan_result =
+        rebuild_plan(cli_opts.rebuild, cli_opts.no_auto_rebuild, &image, &root_dir, &params)?;
+    if rebuild_plan_result.is_none() && image_missing_despite_fresh_marker(backend.as_ref(), &image) {
+        rebuild_plan_result = Some(image.clone());
+    }
+
+    if let Some(rebuild_image) = rebuild_plan_result {
+        let build_spec = prepare_build_spec(
+            &rebuild_image,
+            &root_dir,
+            &params,
+            ctx_sha.as_deref(),
+            uid,
+            gid,
+        )?;
+        if matches!(cli_opts.action, cli::CliAction::PrintCommand) {
+            let mut cmd = vec![backend.binary_name().to_string()];
+            cmd.extend(backend.build_build_args(&build_spec));
+            for arg in cmd {
+                println!("++++ {arg}");
+            }
+        } else {
+            println!("Rebuilding container {rebuild_image}");
+            exec::build_image(backend.as_ref(), &build_spec).map_err(|err| err.to_string())?;
+            persist_rebuild_digest(&root_dir, &params, &rebuild_image)?;
+        }
     }
 
     let mut env_overrides = BTreeMap::new();
@@ -122,10 +172,12 @@ fn run() -> Result<(), String> {
     if let Some(cd_override) = params.get("cd_to").and_then(|vals| vals.first()) {
         cd_to = PathBuf::from(cd_override);
     }
-    mounts.push(internal::Mount {
+    mounts.push(internal::Mount::Bind {
         source: root_dir.clone(),
         target: mount_target.clone(),
         read_only: false,
+        propagation: None,
+        selinux_relabel: None,
         options: Vec::new(),
     });
 
@@ -140,17 +192,19 @@ fn run() -> Result<(), String> {
         }
     }
 
-    if params.contains_key("share_git_dir") && let Some(git_mount) = share_git_dir(&root_dir) {
-        mounts.push(git_mount);
+    if params.contains_key("share_git_dir") {
+        mounts.extend(share_git_dir(&root_dir));
     }
 
     let mut extra_shell_path = None;
     if let Some(extra_shell) = params.get("extra_shell").and_then(|vals| vals.first()) {
         let resolved = resolve_path(extra_shell, &root_dir);
-        mounts.push(internal::Mount {
+        mounts.push(internal::Mount::Bind {
             source: resolved.clone(),
             target: resolved.clone(),
             read_only: false,
+            propagation: None,
+            selinux_relabel: None,
             options: Vec::new(),
         });
         extra_shell_path = Some(resolved);
@@ -162,13 +216,67 @@ fn run() -> Result<(), String> {
         .map(|val| val.as_str());
     let (agent_source, agent_target) =
         resolve_giftwrap_mount(agent_override, &root_dir, &mount_target)?;
-    mounts.push(internal::Mount {
+    mounts.push(internal::Mount::Bind {
         source: agent_source,
         target: agent_target.clone(),
         read_only: true,
+        propagation: None,
+        selinux_relabel: None,
         options: Vec::new(),
     });
 
+    let persistent_home = match params.get("home_volume").and_then(|vals| vals.first()) {
+        Some(home_volume) => {
+            let user_name = resolve_username(uid);
+            let host_path = resolve_home_volume_path(home_volume, &root_dir, &image);
+            std::fs::create_dir_all(&host_path).map_err(|err| {
+                format!(
+                    "Error: failed to create home_volume {}: {err}",
+                    host_path.display()
+                )
+            })?;
+            mounts.push(internal::Mount::Bind {
+                source: host_path,
+                target: build_home(&user_name),
+                read_only: false,
+                propagation: None,
+                selinux_relabel: None,
+                options: Vec::new(),
+            });
+            true
+        }
+        None => false,
+    };
+
+    let mut preserve_fds = 0;
+    if params.contains_key("forward_jobserver") {
+        if let Some(forward) = jobserver::prepare_forward().map_err(|err| err.to_string())? {
+            env_overrides.insert("MAKEFLAGS".to_string(), forward.makeflags);
+            if let Some(mount) = forward.mount {
+                mounts.push(mount);
+            }
+            preserve_fds = forward.preserve_fds;
+        }
+    }
+
+    // Distinct from `forward_jobserver` above: mints a brand new jobserver
+    // giftwrap owns, instead of forwarding a parent `make -jN`'s existing
+    // one. Takes precedence when both are set, since it's the more specific
+    // ask.
+    let mut owned_jobserver = None;
+    if params.contains_key("own_jobserver") {
+        let job_slots = params
+            .get("job_slots")
+            .and_then(|vals| vals.first())
+            .and_then(|val| val.parse::<u32>().ok())
+            .filter(|slots| *slots > 0)
+            .unwrap_or_else(jobserver::default_job_slots);
+        let jobserver = jobserver::create_owned(job_slots).map_err(|err| err.to_string())?;
+        env_overrides.insert("MAKEFLAGS".to_string(), jobserver.makeflags(job_slots));
+        mounts.push(jobserver.mount());
+        owned_jobserver = Some(jobserver);
+    }
+
     let mut extra_args = cli_opts.extra_args.clone();
     let mut config_extra_args = params.get("extra_args").cloned().unwrap_or_default();
     if !cli_opts.runtime_args.is_empty() {
@@ -176,8 +284,7 @@ fn run() -> Result<(), String> {
     }
     extra_args.extend(config_extra_args);
 
-    let uid = unsafe { libc::getuid() } as u32;
-    let gid = unsafe { libc::getgid() } as u32;
+    let rootless = cli_opts.rootless || params.contains_key("gw_rootless");
     let internal_spec = build_internal_spec(
         &root_dir,
         cd_to,
@@ -188,6 +295,8 @@ fn run() -> Result<(), String> {
         extra_shell_path,
         uid,
         gid,
+        rootless,
+        persistent_home,
     );
 
     let internal_spec_json = serde_json::to_string(&internal_spec)
@@ -199,15 +308,21 @@ fn run() -> Result<(), String> {
     container_env.insert("GW_INTERNAL_SPEC".to_string(), internal_spec_json);
 
     let hostname = mkhostname(&image);
+    let (container_user, userns) = rootless_container_args(rootless, uid, gid);
+    let (cap_drop, cap_add) = parse_capabilities(&params);
     let container_spec = internal::ContainerSpec {
         image,
         hostname: Some(hostname),
         mounts,
         env: container_env,
         workdir: None,
-        user: Some("root".to_string()),
+        user: Some(container_user),
         extra_hosts: params.get("extra_hosts").cloned().unwrap_or_default(),
-        privileged: true,
+        userns,
+        privileged: params.contains_key("privileged"),
+        cap_drop,
+        cap_add,
+        no_new_privileges: params.contains_key("no_new_privileges"),
         init: true,
         remove: true,
         interactive,
@@ -215,11 +330,17 @@ fn run() -> Result<(), String> {
         entrypoint: Some(vec![agent_path]),
         command: vec!["agent".to_string()],
         extra_args,
+        preserve_fds,
+        mem_limit: params.get("mem_limit").and_then(|vals| vals.first()).cloned(),
+        cpu_limit: params.get("cpu_limit").and_then(|vals| vals.first()).cloned(),
+        pids_limit: params.get("pids_limit").and_then(|vals| vals.first()).cloned(),
     };
 
     if matches!(cli_opts.action, cli::CliAction::PrintCommand) {
-        let mut cmd = vec!["podman".to_string()];
-        let args = podman_cli::build_run_args(&container_spec).map_err(|err| err.to_string())?;
+        let mut cmd = vec![backend.binary_name().to_string()];
+        let args = backend
+            .build_run_args(&container_spec)
+            .map_err(|err| err.to_string())?;
         cmd.extend(args);
         for arg in cmd {
             println!("++++ {arg}");
@@ -227,7 +348,61 @@ fn run() -> Result<(), String> {
         return Ok(());
     }
 
-    exec::run_container(&container_spec).map_err(|err| err.to_string())
+    let retry_backoff_cap = params
+        .get("retry_backoff_cap_ms")
+        .and_then(|vals| vals.first())
+        .and_then(|val| val.parse::<u64>().ok())
+        .map(std::time::Duration::from_millis);
+    exec::run_container(
+        backend.as_ref(),
+        &container_spec,
+        retry_backoff_cap,
+        owned_jobserver,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Pull `alias_<name>` params out of a config's `params` map into the
+/// `name -> tokens` table `cli::expand_aliases` expects, splitting each
+/// value with `shell_words` the same way `--gw-extra-args` is parsed.
+/// Malformed alias values are dropped rather than erroring, consistent
+/// with this being a best-effort bootstrap pass - a real problem with the
+/// alias still surfaces once it's expanded into nonsense flags.
+fn alias_table(
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> std::collections::HashMap<String, Vec<String>> {
+    let mut aliases = std::collections::HashMap::new();
+    for (key, values) in params {
+        let Some(name) = key.strip_prefix("alias_") else {
+            continue;
+        };
+        let mut tokens = Vec::new();
+        for value in values {
+            match shell_words::split(value) {
+                Ok(parts) => tokens.extend(parts),
+                Err(_) => continue,
+            }
+        }
+        aliases.insert(name.to_string(), tokens);
+    }
+    aliases
+}
+
+/// Resolve the `gw_runtime`/`--gw-runtime=` selection to a `Backend`. With
+/// neither set, probes `$GW_RUNTIME`/`$PATH` (`runtime::detect_default`)
+/// instead of hardcoding Podman, so the same build works unmodified on a
+/// host that only has Docker or nerdctl installed.
+fn select_runtime(
+    params: &std::collections::HashMap<String, Vec<String>>,
+    override_runtime: Option<&str>,
+) -> Result<Box<dyn runtime::Backend>, String> {
+    let name = override_runtime
+        .map(str::to_string)
+        .or_else(|| params.get("gw_runtime").and_then(|vals| vals.first()).cloned());
+    match name {
+        Some(name) => runtime::from_name(&name).map_err(|err| err.to_string()),
+        None => Ok(runtime::detect_default()),
+    }
 }
 
 fn print_help() {
@@ -240,8 +415,32 @@ GW Flags:
     use-ctx: force a particular context sha
     img: force a particular image
     rebuild: rebuild the container image
+    no-auto-rebuild: skip the content-hash staleness check that otherwise rebuilds the image without --gw-rebuild
     show-config: dump the parameters
     extra-args: add extra args to the runtime invocation
+    runtime: force a container runtime backend (podman/docker/nerdctl)
+    rootless: map the host uid/gid in via --userns=keep-id instead of running as root
+    forward_jobserver (config only): forward the parent `make -jN` jobserver from MAKEFLAGS
+    own_jobserver/job_slots (config only): mint a giftwrap-owned fifo jobserver capped at job_slots (default: host CPU count)
+    cap_add/cap_drop (config only): fine-grained --cap-add/--cap-drop list instead of the default drop-all-then-add-back minimum
+    privileged (config only): escape hatch to run with --privileged instead of the cap_add/cap_drop model (default: off)
+    mem_limit/cpu_limit/pids_limit (config only): cap container resources via --memory/--cpus/--pids-limit
+    retry_backoff_cap_ms (config only): cap the exponential backoff between runtime-run retries
+    containerfile_template/build_args (config only): render a templated Containerfile and pass --build-arg pairs
+    build_inputs (config only): extra glob/.d-dep-file paths (relative to root_dir) hashed into the auto-rebuild staleness check
+    home_volume (config only): persist $HOME across runs ("project", "shared", or a literal host path)
+    home_dir (config only): override the container's $HOME instead of the caller's real passwd home directory
+    extra_groups (config only): append extra gids to the container user's supplementary groups
+    resolve_login_shell (config only): populate the shell from the host passwd entry's pw_shell instead of the container's own default
+    workdir_repo_root (config only): contract the sandbox workdir to its enclosing git repository's top-level directory
+    workdir_substitutions (config only): ordered from=to substring substitutions applied to the sandbox workdir
+    context_dirty_check (config only): "mtime" (default) or "fingerprint" staleness detection for version_by_build_context
+    context_hash_mode (config only): "full" (default) or "partial" per-file rehash strategy for version_by_build_context
+    alias_<name> (config only): expand a bare leading word or --gw-alias=<name> into that param's flags
+    no_new_privs (config only): set PR_SET_NO_NEW_PRIVS before exec'ing the shell, blocking setuid/sudo escalation
+    no_new_privileges (config only): emit --security-opt no-new-privileges for the container (opt-in; independent of cap_add/cap_drop, and breaks setuid sudo, including a sudo_allow-scoped grant)
+    sudo_allow/sudo_runas/sudo_arbitrary_args (config only): scope the sudoers grant to an allowlist of commands instead of NOPASSWD: ALL
+    env_allow/env_deny (config only): sanitize inherited environment variables reaching the shell instead of passing everything through
 "#
     );
 }
@@ -257,9 +456,18 @@ fn build_internal_spec(
     extra_shell: Option<PathBuf>,
     uid: u32,
     gid: u32,
+    rootless: bool,
+    persistent_home: bool,
 ) -> internal::InternalSpec {
+    let workdir = normalize_workdir(workdir, params);
     let user_name = resolve_username(uid);
-    let user_home = build_home(&user_name);
+    let user_home = resolve_home(&user_name, uid, params);
+    let mut groups = resolve_groups(&user_name, gid);
+    for extra in parse_extra_groups(params) {
+        if !groups.contains(&extra) {
+            groups.push(extra);
+        }
+    }
     let persist_env = params
         .get("persist_environment")
         .and_then(|vals| vals.first())
@@ -278,15 +486,154 @@ fn build_internal_spec(
             uid,
             gid,
             home: user_home,
+            groups,
+            persistent_home,
         },
         env_overrides,
         persist_env,
         terminfo,
         command,
-        shell: None,
+        shell: resolve_login_shell(uid, params),
         extra_shell,
         prefix_cmd: params.get("prefix_cmd").cloned().unwrap_or_default(),
         prefix_cmd_quiet: params.get("prefix_cmd_quiet").cloned().unwrap_or_default(),
+        pty: params.contains_key("pty"),
+        privilege_backend: parse_privilege_backend(params),
+        rootless,
+        no_new_privs: params.contains_key("no_new_privs"),
+        sudo_policy: parse_sudo_policy(params),
+        env_filter: parse_env_filter(params),
+    }
+}
+
+/// Normalize the sandbox workdir before it's serialized: optionally contract
+/// it to its enclosing git repository's top-level directory (`workdir_repo_root`),
+/// so every command starts at a stable root regardless of which
+/// subdirectory the caller happened to invoke giftwrap from, then apply any
+/// `workdir_substitutions` (e.g. rewriting a mount path prefix). Both are
+/// off by default, so callers that configure neither see the workdir
+/// serialized exactly as passed in.
+fn normalize_workdir(
+    workdir: PathBuf,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> PathBuf {
+    let mut workdir = workdir;
+    if params.contains_key("workdir_repo_root") && let Some(top_level) = git_repo_toplevel(&workdir)
+    {
+        workdir = top_level;
+    }
+    if let Some(substitutions) = params.get("workdir_substitutions") {
+        workdir = apply_path_substitutions(&workdir, substitutions);
+    }
+    workdir
+}
+
+/// The canonicalized top-level directory of the git repository containing
+/// `path`, or `None` if `path` isn't inside a git working tree (or `git`
+/// isn't available).
+fn git_repo_toplevel(path: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let raw = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if raw.is_empty() {
+        return None;
+    }
+    let candidate = PathBuf::from(raw);
+    Some(std::fs::canonicalize(&candidate).unwrap_or(candidate))
+}
+
+/// Apply an ordered list of `from=to` substring substitutions to `path`,
+/// each applied to the result of the previous one - for rewriting a mount
+/// path prefix (e.g. a host path the runtime remaps to a different
+/// in-container location).
+fn apply_path_substitutions(path: &Path, substitutions: &[String]) -> PathBuf {
+    let mut value = path.to_string_lossy().into_owned();
+    for entry in substitutions {
+        if let Some((from, to)) = entry.split_once('=') {
+            value = value.replace(from, to);
+        }
+    }
+    PathBuf::from(value)
+}
+
+/// Build an `env_filter` from `env_allow`/`env_deny` config params. Returns
+/// `None` (inherit everything) when neither is set.
+fn parse_env_filter(
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Option<internal::EnvFilter> {
+    let allow = params.get("env_allow").cloned().unwrap_or_default();
+    let deny = params.get("env_deny").cloned().unwrap_or_default();
+    if allow.is_empty() && deny.is_empty() {
+        return None;
+    }
+    Some(internal::EnvFilter { allow, deny })
+}
+
+/// Build a `sudo_policy` from `sudo_allow`/`sudo_runas`/`sudo_arbitrary_args`
+/// config params. Returns `None` (the backward-compatible blanket grant)
+/// when `sudo_allow` is unset or empty.
+fn parse_sudo_policy(
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Option<internal::SudoPolicy> {
+    let commands = params.get("sudo_allow")?.clone();
+    if commands.is_empty() {
+        return None;
+    }
+    let runas = params
+        .get("sudo_runas")
+        .and_then(|vals| vals.first())
+        .cloned()
+        .unwrap_or_else(|| "ALL".to_string());
+    Some(internal::SudoPolicy {
+        runas,
+        commands,
+        arbitrary_args: params.contains_key("sudo_arbitrary_args"),
+    })
+}
+
+/// Default "drop everything, then add back the bare minimum" capability set
+/// applied when `cap_add`/`cap_drop` are both left unconfigured, so the
+/// common unprivileged case still runs build tooling that expects to chown/
+/// chmod/setuid its own files.
+const DEFAULT_CAP_DROP: &[&str] = &["ALL"];
+const DEFAULT_CAP_ADD: &[&str] = &["CHOWN", "SETUID", "SETGID", "DAC_OVERRIDE"];
+
+/// Resolve `cap_add`/`cap_drop` config params into the lists `ContainerSpec`
+/// carries. Falls back to `DEFAULT_CAP_DROP`/`DEFAULT_CAP_ADD` when neither
+/// is set, and otherwise takes each param exactly as configured (an empty
+/// `cap_drop` with some `cap_add` is honored as-is, not merged with the
+/// default).
+fn parse_capabilities(
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> (Vec<String>, Vec<String>) {
+    if !params.contains_key("cap_add") && !params.contains_key("cap_drop") {
+        return (
+            DEFAULT_CAP_DROP.iter().map(|cap| cap.to_string()).collect(),
+            DEFAULT_CAP_ADD.iter().map(|cap| cap.to_string()).collect(),
+        );
+    }
+    (
+        params.get("cap_drop").cloned().unwrap_or_default(),
+        params.get("cap_add").cloned().unwrap_or_default(),
+    )
+}
+
+fn parse_privilege_backend(
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> internal::PrivilegeBackend {
+    match params
+        .get("privilege_backend")
+        .and_then(|vals| vals.first())
+        .map(String::as_str)
+    {
+        Some("pam") => internal::PrivilegeBackend::Pam,
+        _ => internal::PrivilegeBackend::Sudoers,
     }
 }
 
@@ -309,14 +656,209 @@ fn select_image(
     Ok(image)
 }
 
-fn rebuild_plan(rebuild: bool, image: &str) -> Option<String> {
-    if rebuild {
-        Some(image.to_string())
+/// Translate the rootless setting into the `ContainerSpec` bits it affects:
+/// the `-u` value and the `--userns` mapping. Rootless maps the host uid/gid
+/// into the container via `keep-id` instead of running as root and dropping
+/// privileges later. Whether the container also gets `--privileged` or the
+/// fine-grained `cap_add`/`cap_drop` model is a separate, orthogonal setting
+/// - see `parse_capabilities`.
+fn rootless_container_args(rootless: bool, uid: u32, gid: u32) -> (String, Option<String>) {
+    if rootless {
+        (
+            format!("{uid}:{gid}"),
+            Some(format!("keep-id:uid={uid},gid={gid}")),
+        )
     } else {
-        None
+        ("root".to_string(), None)
     }
 }
 
+/// Decide whether `image` needs a rebuild: always when `--gw-rebuild` is
+/// passed, otherwise (unless `--gw-no-auto-rebuild` opts out) by hashing the
+/// Containerfile/Dockerfile plus the `build_inputs` config param against the
+/// digest stamped from the last successful build, so a stale image doesn't
+/// silently persist just because nobody remembered to pass `--gw-rebuild`.
+fn rebuild_plan(
+    rebuild: bool,
+    no_auto_rebuild: bool,
+    image: &str,
+    root_dir: &Path,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Result<Option<String>, String> {
+    if rebuild {
+        return Ok(Some(image.to_string()));
+    }
+    if no_auto_rebuild {
+        return Ok(None);
+    }
+
+    let inputs = collect_rebuild_inputs(root_dir, params)?;
+    if inputs.is_empty() {
+        return Ok(None);
+    }
+    let digest = staleness::compute_digest(root_dir, &inputs).map_err(|err| err.to_string())?;
+    let marker = rebuild_marker_path(root_dir, image);
+    if staleness::read_marker(&marker).as_deref() == Some(digest.as_str()) {
+        return Ok(None);
+    }
+    Ok(Some(image.to_string()))
+}
+
+/// Whether `rebuild_plan` can be trusted to have skipped a rebuild: it only
+/// compares build-input digests, so a matching digest doesn't prove the
+/// image itself is still there - it could have been removed by `podman rmi`,
+/// pruned storage, or a fresh checkout on another machine. Called only when
+/// `rebuild_plan` already returned `None`, to catch that case before it
+/// turns into a confusing "no such image" failure further down. Any probe
+/// failure (runtime not on `PATH`, unexpected output, ...) is treated as
+/// "can't confirm either way" and leaves the skip in place, since forcing a
+/// hard error out of what's meant to be a staleness optimization would be
+/// worse than occasionally trusting a stale marker.
+fn image_missing_despite_fresh_marker(backend: &dyn runtime::Backend, image: &str) -> bool {
+    matches!(exec::image_exists(backend, image), Ok(false))
+}
+
+/// Stamp the digest of `image`'s current build inputs at its marker path
+/// after a successful build, so the next invocation's `rebuild_plan` sees it
+/// as fresh. A no-op when there's nothing configured to hash.
+fn persist_rebuild_digest(
+    root_dir: &Path,
+    params: &std::collections::HashMap<String, Vec<String>>,
+    image: &str,
+) -> Result<(), String> {
+    let inputs = collect_rebuild_inputs(root_dir, params)?;
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    let digest = staleness::compute_digest(root_dir, &inputs).map_err(|err| err.to_string())?;
+    staleness::write_marker(&rebuild_marker_path(root_dir, image), &digest)
+        .map_err(|err| err.to_string())
+}
+
+/// The Containerfile/Dockerfile and `build_inputs` glob/`.d`-dep-file
+/// matches a staleness check should hash, per [`resolve_rebuild_containerfile`].
+fn collect_rebuild_inputs(
+    root_dir: &Path,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Result<Vec<PathBuf>, String> {
+    let containerfile = resolve_rebuild_containerfile(root_dir, params);
+    let build_inputs = params.get("build_inputs").cloned().unwrap_or_default();
+    staleness::collect_build_inputs(root_dir, containerfile.as_deref(), &build_inputs)
+        .map_err(|err| err.to_string())
+}
+
+/// Resolve the file a staleness check treats as "the Containerfile": the
+/// `containerfile_template` *source* (not `prepare_build_spec`'s ephemeral
+/// rendered copy, which is always freshly written and so never stale) when
+/// configured, else a literal `Containerfile`/`Dockerfile` in `root_dir`.
+fn resolve_rebuild_containerfile(
+    root_dir: &Path,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Option<PathBuf> {
+    if let Some(template_path) = params
+        .get("containerfile_template")
+        .and_then(|vals| vals.first())
+    {
+        return Some(resolve_real_path(template_path, root_dir));
+    }
+    ["Containerfile", "Dockerfile"]
+        .into_iter()
+        .map(|name| root_dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Where a given image's build-input digest is stamped between runs.
+fn rebuild_marker_path(root_dir: &Path, image: &str) -> PathBuf {
+    root_dir
+        .join("target")
+        .join(format!(
+            ".giftwrap-build-digest-{}",
+            sanitize_path_segment(image)
+        ))
+}
+
+/// Resolve `containerfile_template`/`build_args` into a `BuildSpec`,
+/// rendering the template (if configured) with `{{ image }}`/`{{ ctx }}`/
+/// `{{ uid }}`/`{{ gid }}`/`{{ user }}` so a project can pin the build user
+/// and package flags into the image instead of hand-editing a
+/// Containerfile per checkout.
+fn prepare_build_spec(
+    image: &str,
+    root_dir: &Path,
+    params: &std::collections::HashMap<String, Vec<String>>,
+    ctx_sha: Option<&str>,
+    uid: u32,
+    gid: u32,
+) -> Result<internal::BuildSpec, String> {
+    let build_args = params
+        .get("build_args")
+        .map(|entries| parse_build_args(entries))
+        .unwrap_or_default();
+
+    let dockerfile = match params
+        .get("containerfile_template")
+        .and_then(|vals| vals.first())
+    {
+        Some(template_path) => {
+            let resolved = resolve_real_path(template_path, root_dir);
+            let contents = std::fs::read_to_string(&resolved).map_err(|err| {
+                format!(
+                    "Error: failed to read containerfile_template {}: {err}",
+                    resolved.display()
+                )
+            })?;
+            let user = resolve_username(uid);
+            let rendered = render_containerfile(&contents, image, ctx_sha, uid, gid, &user);
+            let rendered_path =
+                std::env::temp_dir().join(format!("gw-containerfile-{}", std::process::id()));
+            std::fs::write(&rendered_path, rendered).map_err(|err| {
+                format!(
+                    "Error: failed to write rendered containerfile to {}: {err}",
+                    rendered_path.display()
+                )
+            })?;
+            Some(rendered_path)
+        }
+        None => None,
+    };
+
+    Ok(internal::BuildSpec {
+        image: image.to_string(),
+        context_dir: root_dir.to_path_buf(),
+        dockerfile,
+        build_args,
+    })
+}
+
+/// Parse `key=value` pairs from the `build_args` config param into a
+/// `--build-arg` map.
+fn parse_build_args(entries: &[String]) -> std::collections::BTreeMap<String, String> {
+    entries
+        .iter()
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Render a Containerfile template's `{{ name }}` tokens against the
+/// resolved build/run context.
+fn render_containerfile(
+    template_contents: &str,
+    image: &str,
+    ctx_sha: Option<&str>,
+    uid: u32,
+    gid: u32,
+    user: &str,
+) -> String {
+    let mut vars = std::collections::BTreeMap::new();
+    vars.insert("image".to_string(), image.to_string());
+    vars.insert("ctx".to_string(), ctx_sha.unwrap_or_default().to_string());
+    vars.insert("uid".to_string(), uid.to_string());
+    vars.insert("gid".to_string(), gid.to_string());
+    vars.insert("user".to_string(), user.to_string());
+    template::render(template_contents, &vars)
+}
+
 fn run_hook(hook: &[String], root_dir: &Path) -> Result<(), String> {
     if hook.is_empty() {
         return Ok(());
@@ -366,10 +908,12 @@ fn parse_share(share: &str, root_dir: &Path) -> Option<internal::Mount> {
     }
     if parts.len() == 1 {
         let source = abs_path(parts[0], root_dir);
-        return Some(internal::Mount {
+        return Some(internal::Mount::Bind {
             source: source.clone(),
             target: source,
             read_only: false,
+            propagation: None,
+            selinux_relabel: None,
             options: Vec::new(),
         });
     }
@@ -384,35 +928,112 @@ fn parse_share(share: &str, root_dir: &Path) -> Option<internal::Mount> {
     } else {
         Vec::new()
     };
-    Some(internal::Mount {
+    Some(internal::Mount::Bind {
         source,
         target,
         read_only: false,
+        propagation: None,
+        selinux_relabel: None,
         options,
     })
 }
 
-fn share_git_dir(root_dir: &Path) -> Option<internal::Mount> {
-    let output = Command::new("git")
-        .arg("rev-parse")
-        .arg("--git-common-dir")
-        .current_dir(root_dir)
-        .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
+/// Mounts needed to share the host's git metadata into the container: the
+/// repo's gitdir (read from `root_dir/.git`, whether that's a plain
+/// directory or a `gitdir:` pointer file as written by `--separate-git-dir`
+/// or a linked worktree), the main repo's common dir for a linked worktree
+/// (read from the gitdir's `commondir` file, since a worktree's object store
+/// and refs live there rather than in its own private gitdir), and each
+/// submodule's gitdir under `.git/modules/<name>` (read from `.gitmodules`
+/// and each submodule's own `gitdir:` pointer file), so `git status`/`git
+/// submodule` keep working inside the sandbox. Any of these that resolves
+/// inside `root_dir` is dropped, matching the existing "repo's gitdir
+/// already lives inside the sandbox" skip behavior - which is also why a
+/// plain (non-worktree, non-separate-git-dir) checkout mounts nothing extra:
+/// `.git` and `.git/modules/*` already live under the sandboxed `root_dir`.
+fn share_git_dir(root_dir: &Path) -> Vec<internal::Mount> {
+    let Some(git_dir) = resolve_git_dir(root_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths = vec![git_dir.clone()];
+    if let Some(common_dir) = read_commondir(&git_dir) {
+        if common_dir != git_dir {
+            paths.push(common_dir);
+        }
     }
-    let raw = String::from_utf8_lossy(&output.stdout);
-    let git_dir = abs_path(raw.trim(), root_dir);
-    if git_dir.starts_with(root_dir) {
+    paths.extend(submodule_git_dirs(root_dir));
+
+    paths
+        .into_iter()
+        .filter(|path| !path.starts_with(root_dir))
+        .map(|path| internal::Mount::Bind {
+            source: path.clone(),
+            target: path,
+            read_only: false,
+            propagation: None,
+            selinux_relabel: None,
+            options: Vec::new(),
+        })
+        .collect()
+}
+
+/// Resolve every submodule listed in `root_dir/.gitmodules` to its actual
+/// gitdir, by reading each submodule path's own `.git` pointer file the same
+/// way `resolve_git_dir` reads the superproject's. Submodules that haven't
+/// been initialized (no `.git` file at their path yet) are silently skipped.
+fn submodule_git_dirs(root_dir: &Path) -> Vec<PathBuf> {
+    let Ok(contents) = std::fs::read_to_string(root_dir.join(".gitmodules")) else {
+        return Vec::new();
+    };
+    parse_gitmodules_paths(&contents)
+        .into_iter()
+        .filter_map(|rel_path| resolve_git_dir(&root_dir.join(rel_path)))
+        .collect()
+}
+
+/// Extract each `path = ...` value from a `.gitmodules` file's `[submodule
+/// "name"]` sections - the only field needed to locate a submodule's
+/// checkout, ignoring `url`/`branch`/every other key.
+fn parse_gitmodules_paths(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            (key.trim() == "path").then(|| value.trim().to_string())
+        })
+        .collect()
+}
+
+/// Resolve `root_dir/.git` to the gitdir it actually points at: itself, if
+/// it's a plain directory, or the canonicalized `gitdir: <path>` target if
+/// it's a pointer file. `None` if there's no `.git` entry at all.
+fn resolve_git_dir(root_dir: &Path) -> Option<PathBuf> {
+    let dot_git = root_dir.join(".git");
+    let metadata = std::fs::symlink_metadata(&dot_git).ok()?;
+    let target = if metadata.is_dir() {
+        dot_git
+    } else {
+        let contents = std::fs::read_to_string(&dot_git).ok()?;
+        let raw = contents.trim().strip_prefix("gitdir:")?.trim();
+        abs_path(raw, root_dir)
+    };
+    Some(std::fs::canonicalize(&target).unwrap_or(target))
+}
+
+/// Read a linked worktree's `commondir` file (its gitdir's pointer back to
+/// the main repo's shared `.git`), resolving a relative value against
+/// `git_dir` per the `commondir` file format. `None` when there's no
+/// `commondir` file (a non-worktree gitdir) or it's `.` (already the common
+/// dir - a single-repo checkout with no separate common dir to mount).
+fn read_commondir(git_dir: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(git_dir.join("commondir")).ok()?;
+    let raw = contents.trim();
+    if raw.is_empty() || raw == "." {
         return None;
     }
-    Some(internal::Mount {
-        source: git_dir.clone(),
-        target: git_dir,
-        read_only: false,
-        options: Vec::new(),
-    })
+    let candidate = abs_path(raw, git_dir);
+    Some(std::fs::canonicalize(&candidate).unwrap_or(candidate))
 }
 
 fn abs_path(path: &str, root_dir: &Path) -> PathBuf {
@@ -574,10 +1195,161 @@ fn resolve_username(uid: u32) -> String {
     uid.to_string()
 }
 
+/// Resolve the supplementary group ids for `user` via `getgrouplist`, so the
+/// container gains the same group memberships (docker, wheel, video, ...) the
+/// caller has on the host. Returns an empty vec (clearing the inherited root
+/// group set) if the lookup fails.
+///
+/// This resolves on the host rather than calling `initgroups` from inside
+/// the agent: the agent runs after the container's rootfs is already
+/// mounted, and the target account's `/etc/group` memberships there may not
+/// match (or even contain) the host account this spec was built from. The
+/// resolved gid list crosses the host/agent boundary on `UserSpec::groups`
+/// so `drop_privileges` only has to `setgroups` it, not look anything up.
+fn resolve_groups(user: &str, gid: u32) -> Vec<u32> {
+    let Ok(c_user) = CString::new(user) else {
+        return Vec::new();
+    };
+    let mut ngroups: libc::c_int = 32;
+    loop {
+        let mut groups: Vec<libc::gid_t> = vec![0; ngroups as usize];
+        let result = unsafe {
+            libc::getgrouplist(
+                c_user.as_ptr(),
+                gid as libc::gid_t,
+                groups.as_mut_ptr(),
+                &mut ngroups,
+            )
+        };
+        if result >= 0 {
+            groups.truncate(ngroups as usize);
+            return groups.into_iter().map(|g| g as u32).collect();
+        }
+        if ngroups > (1 << 16) {
+            return Vec::new();
+        }
+        ngroups *= 2;
+    }
+}
+
 fn build_home(user: &str) -> PathBuf {
     PathBuf::from(format!("/tmp/dr-tmp-home-{user}/{user}"))
 }
 
+/// Resolve the container's `$HOME`: an explicit `home_dir` config override
+/// wins, then the caller's real passwd home directory (so build tooling that
+/// expects a realistic `$HOME` layout, e.g. `~/.cargo`, behaves the same as
+/// on the host), falling back to `build_home`'s synthesized path when the
+/// passwd lookup fails (e.g. the uid has no host account at all).
+fn resolve_home(
+    user: &str,
+    uid: u32,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> PathBuf {
+    if let Some(home_dir) = params.get("home_dir").and_then(|vals| vals.first()) {
+        return PathBuf::from(home_dir);
+    }
+    passwd_home(uid).unwrap_or_else(|| build_home(user))
+}
+
+/// The `pw_dir` field of the host passwd entry for `uid`, or `None` if there
+/// is no such entry or its home directory is empty.
+fn passwd_home(uid: u32) -> Option<PathBuf> {
+    unsafe {
+        let pwd = libc::getpwuid(uid as libc::uid_t);
+        if pwd.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*pwd).pw_dir)
+            .to_string_lossy()
+            .into_owned();
+        if dir.is_empty() { None } else { Some(PathBuf::from(dir)) }
+    }
+}
+
+/// Resolve the container's login shell from the host passwd entry's
+/// `pw_shell`, when the opt-in `resolve_login_shell` config param is set.
+/// Defaults to `None` (the agent's own container-side `pw_shell`/`$SHELL`
+/// fallback, see `select_shell`) otherwise, so callers that never asked for
+/// this keep their current behavior.
+fn resolve_login_shell(
+    uid: u32,
+    params: &std::collections::HashMap<String, Vec<String>>,
+) -> Option<String> {
+    if !params.contains_key("resolve_login_shell") {
+        return None;
+    }
+    passwd_shell(uid)
+}
+
+/// The `pw_shell` field of the host passwd entry for `uid`, or `None` if
+/// there is no such entry or its shell is empty.
+fn passwd_shell(uid: u32) -> Option<String> {
+    unsafe {
+        let pwd = libc::getpwuid(uid as libc::uid_t);
+        if pwd.is_null() {
+            return None;
+        }
+        let shell = CStr::from_ptr((*pwd).pw_shell)
+            .to_string_lossy()
+            .into_owned();
+        if shell.is_empty() { None } else { Some(shell) }
+    }
+}
+
+/// Resolve extra supplementary group ids from the `extra_groups` config
+/// param, on top of whatever `resolve_groups` already found via the host
+/// group database - for memberships (e.g. `docker`, `kvm`) granted to the
+/// invoking uid only inside the container, not on the host running
+/// giftwrap. Entries that don't parse as a gid are skipped.
+fn parse_extra_groups(params: &std::collections::HashMap<String, Vec<String>>) -> Vec<u32> {
+    params
+        .get("extra_groups")
+        .map(|vals| vals.iter().filter_map(|val| val.parse::<u32>().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Resolve a `home_volume` config value to the host directory bind-mounted
+/// onto the container's `$HOME`, complementing `persist_environment` (which
+/// only persists env vars, not on-disk home state): `"project"` for a path
+/// under `root_dir` shared by everyone building this checkout, `"shared"`
+/// for a path under the XDG cache dir keyed by image (so unrelated projects
+/// using the same image share one home), or any other value taken as a
+/// literal host path.
+fn resolve_home_volume_path(value: &str, root_dir: &Path, image: &str) -> PathBuf {
+    match value {
+        "project" => root_dir.join(".gw-home"),
+        "shared" => xdg_cache_dir()
+            .join("giftwrap")
+            .join(sanitize_path_segment(image)),
+        other => PathBuf::from(other),
+    }
+}
+
+fn xdg_cache_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("XDG_CACHE_HOME") && !dir.is_empty() {
+        return PathBuf::from(dir);
+    }
+    std::env::var("HOME")
+        .map(|home| PathBuf::from(home).join(".cache"))
+        .unwrap_or_else(|_| PathBuf::from("/tmp"))
+}
+
+/// Sanitize a value (e.g. an image reference, which may contain `/` and
+/// `:`) for safe use as a single path segment.
+fn sanitize_path_segment(value: &str) -> String {
+    value
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+                ch
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
 fn load_terminfo(term: &str) -> Result<internal::TerminfoSpec, String> {
     let output = Command::new("infocmp")
         .arg(term)
@@ -605,8 +1377,12 @@ fn format_exit_status(status: &std::process::ExitStatus) -> String {
 #[cfg(test)]
 mod tests {
     use super::{
-        abs_path, build_internal_spec, expand_share, mkhostname, parse_share, rebuild_plan,
-        resolve_path, resolve_real_path, select_image, share_git_dir,
+        abs_path, apply_path_substitutions, build_home, build_internal_spec, expand_share,
+        mkhostname, parse_build_args, parse_capabilities, parse_extra_groups, parse_share,
+        passwd_shell, persist_rebuild_digest, prepare_build_spec, rebuild_plan,
+        render_containerfile, resolve_groups, resolve_home, resolve_home_volume_path,
+        resolve_login_shell, resolve_path, resolve_real_path, rootless_container_args,
+        sanitize_path_segment, select_image, share_git_dir,
     };
     use crate::internal;
     use serde_json::Value;
@@ -705,11 +1481,324 @@ mod tests {
         assert_eq!(err, "Error: gw_container must be specified");
     }
 
+    #[test]
+    fn parse_privilege_backend_defaults_to_sudoers() {
+        let params = HashMap::new();
+        assert_eq!(
+            parse_privilege_backend(&params),
+            internal::PrivilegeBackend::Sudoers
+        );
+    }
+
+    #[test]
+    fn parse_privilege_backend_selects_pam() {
+        let mut params = HashMap::new();
+        params.insert("privilege_backend".to_string(), vec!["pam".to_string()]);
+        assert_eq!(
+            parse_privilege_backend(&params),
+            internal::PrivilegeBackend::Pam
+        );
+    }
+
+    #[test]
+    fn rootless_container_args_maps_keep_id() {
+        let (user, userns) = rootless_container_args(true, 1000, 1000);
+        assert_eq!(user, "1000:1000");
+        assert_eq!(userns.as_deref(), Some("keep-id:uid=1000,gid=1000"));
+    }
+
+    #[test]
+    fn rootless_container_args_defaults_to_root() {
+        let (user, userns) = rootless_container_args(false, 1000, 1000);
+        assert_eq!(user, "root");
+        assert!(userns.is_none());
+    }
+
+    #[test]
+    fn parse_capabilities_defaults_to_drop_all_add_back_minimum() {
+        let params = HashMap::new();
+        let (cap_drop, cap_add) = parse_capabilities(&params);
+        assert_eq!(cap_drop, vec!["ALL".to_string()]);
+        assert_eq!(
+            cap_add,
+            vec!["CHOWN", "SETUID", "SETGID", "DAC_OVERRIDE"]
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_capabilities_honors_explicit_configuration() {
+        let mut params = HashMap::new();
+        params.insert("cap_add".to_string(), vec!["NET_BIND_SERVICE".to_string()]);
+        let (cap_drop, cap_add) = parse_capabilities(&params);
+        assert!(cap_drop.is_empty());
+        assert_eq!(cap_add, vec!["NET_BIND_SERVICE".to_string()]);
+    }
+
     #[test]
     fn rebuild_plan_returns_image_when_enabled() {
+        let root = TempDir::new().expect("tempdir");
         let image = "registry/app:tag";
-        assert_eq!(rebuild_plan(false, image), None);
-        assert_eq!(rebuild_plan(true, image), Some(image.to_string()));
+        let params = HashMap::new();
+        assert_eq!(
+            rebuild_plan(false, false, image, root.path(), &params),
+            Ok(None)
+        );
+        assert_eq!(
+            rebuild_plan(true, false, image, root.path(), &params),
+            Ok(Some(image.to_string()))
+        );
+    }
+
+    #[test]
+    fn rebuild_plan_triggers_on_containerfile_change_and_settles_after_persist() {
+        let root = TempDir::new().expect("tempdir");
+        std::fs::write(root.path().join("Containerfile"), "FROM scratch").unwrap();
+        let image = "registry/app:tag";
+        let params = HashMap::new();
+
+        assert_eq!(
+            rebuild_plan(false, false, image, root.path(), &params),
+            Ok(Some(image.to_string())),
+            "no marker yet, so a Containerfile should look stale"
+        );
+
+        persist_rebuild_digest(root.path(), &params, image).expect("persist_rebuild_digest");
+        assert_eq!(rebuild_plan(false, false, image, root.path(), &params), Ok(None));
+
+        std::fs::write(root.path().join("Containerfile"), "FROM scratch AS changed").unwrap();
+        assert_eq!(
+            rebuild_plan(false, false, image, root.path(), &params),
+            Ok(Some(image.to_string()))
+        );
+    }
+
+    #[test]
+    fn rebuild_plan_honors_no_auto_rebuild() {
+        let root = TempDir::new().expect("tempdir");
+        std::fs::write(root.path().join("Containerfile"), "FROM scratch").unwrap();
+        let params = HashMap::new();
+        assert_eq!(
+            rebuild_plan(false, true, "registry/app:tag", root.path(), &params),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn rebuild_plan_is_unstaleable_without_any_inputs_configured() {
+        let root = TempDir::new().expect("tempdir");
+        let params = HashMap::new();
+        assert_eq!(
+            rebuild_plan(false, false, "registry/app:tag", root.path(), &params),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn parse_build_args_splits_key_value_pairs_and_skips_malformed() {
+        let args = parse_build_args(&[
+            "PKGS=git curl".to_string(),
+            "UID=1000".to_string(),
+            "malformed".to_string(),
+        ]);
+        assert_eq!(args.len(), 2);
+        assert_eq!(args.get("PKGS").map(String::as_str), Some("git curl"));
+        assert_eq!(args.get("UID").map(String::as_str), Some("1000"));
+    }
+
+    #[test]
+    fn render_containerfile_substitutes_resolved_vars() {
+        let rendered = render_containerfile(
+            "FROM {{ image }}\nARG CTX={{ctx}}\nRUN useradd -u {{ uid }} -g {{ gid }} {{ user }}",
+            "registry/app:deadbeef",
+            Some("deadbeef"),
+            1000,
+            1000,
+            "dev",
+        );
+        assert_eq!(
+            rendered,
+            "FROM registry/app:deadbeef\nARG CTX=deadbeef\nRUN useradd -u 1000 -g 1000 dev"
+        );
+    }
+
+    #[test]
+    fn prepare_build_spec_renders_template_and_build_args() {
+        let root = TempDir::new().expect("tempdir");
+        let template_path = root.path().join("Containerfile.tmpl");
+        std::fs::write(&template_path, "FROM {{ image }}\nUSER {{ user }}").expect("write template");
+
+        let mut params = HashMap::new();
+        params.insert(
+            "containerfile_template".to_string(),
+            vec!["Containerfile.tmpl".to_string()],
+        );
+        params.insert(
+            "build_args".to_string(),
+            vec!["PKGS=git".to_string()],
+        );
+
+        let spec = prepare_build_spec(
+            "registry/app:tag",
+            root.path(),
+            &params,
+            Some("deadbeef"),
+            1000,
+            1000,
+        )
+        .expect("prepare_build_spec failed");
+
+        assert_eq!(spec.image, "registry/app:tag");
+        assert_eq!(spec.build_args.get("PKGS").map(String::as_str), Some("git"));
+        let dockerfile = spec.dockerfile.expect("expected rendered dockerfile path");
+        let rendered = std::fs::read_to_string(&dockerfile).expect("read rendered dockerfile");
+        assert!(rendered.contains("FROM registry/app:tag"));
+    }
+
+    #[test]
+    fn prepare_build_spec_without_template_has_no_dockerfile() {
+        let root = TempDir::new().expect("tempdir");
+        let params = HashMap::new();
+
+        let spec = prepare_build_spec("busybox", root.path(), &params, None, 1000, 1000)
+            .expect("prepare_build_spec failed");
+        assert!(spec.dockerfile.is_none());
+        assert!(spec.build_args.is_empty());
+    }
+
+    #[test]
+    fn resolve_home_volume_path_handles_project_shared_and_literal() {
+        let _lock = lock_env();
+        let prior_xdg = std::env::var("XDG_CACHE_HOME").ok();
+        unsafe {
+            std::env::set_var("XDG_CACHE_HOME", "/cache");
+        }
+
+        let root = TempDir::new().expect("tempdir");
+
+        assert_eq!(
+            resolve_home_volume_path("project", root.path(), "registry/app:tag"),
+            root.path().join(".gw-home")
+        );
+        assert_eq!(
+            resolve_home_volume_path("shared", root.path(), "registry/app:tag"),
+            PathBuf::from("/cache/giftwrap/registry-app-tag")
+        );
+        assert_eq!(
+            resolve_home_volume_path("/srv/gw-home", root.path(), "registry/app:tag"),
+            PathBuf::from("/srv/gw-home")
+        );
+
+        match prior_xdg {
+            Some(value) => unsafe { std::env::set_var("XDG_CACHE_HOME", value) },
+            None => unsafe { std::env::remove_var("XDG_CACHE_HOME") },
+        }
+    }
+
+    #[test]
+    fn resolve_groups_returns_empty_for_name_with_interior_nul() {
+        assert_eq!(resolve_groups("bad\0name", 1000), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn resolve_home_honors_home_dir_override() {
+        let mut params = HashMap::new();
+        params.insert("home_dir".to_string(), vec!["/srv/home/dev".to_string()]);
+        assert_eq!(
+            resolve_home("dev", 0, &params),
+            PathBuf::from("/srv/home/dev")
+        );
+    }
+
+    #[test]
+    fn resolve_home_falls_back_to_synthesized_path_for_unknown_uid() {
+        let params = HashMap::new();
+        assert_eq!(
+            resolve_home("gw-user", u32::MAX, &params),
+            build_home("gw-user")
+        );
+    }
+
+    #[test]
+    fn resolve_login_shell_defaults_to_none_without_opt_in() {
+        let params = HashMap::new();
+        let uid = unsafe { libc::getuid() } as u32;
+        assert_eq!(resolve_login_shell(uid, &params), None);
+    }
+
+    #[test]
+    fn resolve_login_shell_returns_host_pw_shell_when_opted_in() {
+        let uid = unsafe { libc::getuid() } as u32;
+        let Some(expected) = passwd_shell(uid) else {
+            return;
+        };
+        let mut params = HashMap::new();
+        params.insert("resolve_login_shell".to_string(), Vec::new());
+        assert_eq!(resolve_login_shell(uid, &params), Some(expected));
+    }
+
+    #[test]
+    fn parse_extra_groups_skips_unparsable_entries() {
+        let mut params = HashMap::new();
+        params.insert(
+            "extra_groups".to_string(),
+            vec!["999".to_string(), "docker".to_string(), "998".to_string()],
+        );
+        assert_eq!(parse_extra_groups(&params), vec![999, 998]);
+    }
+
+    #[test]
+    fn apply_path_substitutions_chains_entries_in_order() {
+        let path = PathBuf::from("/host/mnt/project");
+        let substitutions = vec![
+            "/host/mnt=/workspace".to_string(),
+            "/workspace/project=/workspace/app".to_string(),
+        ];
+        assert_eq!(
+            apply_path_substitutions(&path, &substitutions),
+            PathBuf::from("/workspace/app")
+        );
+    }
+
+    #[test]
+    fn internal_spec_merges_extra_groups_without_duplicates() {
+        let root = TempDir::new().expect("tempdir");
+        let root_dir = root.path().canonicalize().expect("canonicalize root");
+        let mut params = HashMap::new();
+        params.insert(
+            "extra_groups".to_string(),
+            vec!["65533".to_string(), "65533".to_string()],
+        );
+
+        let spec = build_internal_spec(
+            &root_dir,
+            root_dir.join("work"),
+            vec!["true".to_string()],
+            BTreeMap::new(),
+            &params,
+            None,
+            None,
+            0,
+            0,
+            false,
+            false,
+        );
+
+        assert_eq!(
+            spec.user.groups.iter().filter(|gid| **gid == 65533).count(),
+            1,
+            "a configured extra_groups gid should appear exactly once even if repeated"
+        );
+    }
+
+    #[test]
+    fn sanitize_path_segment_replaces_unsafe_chars() {
+        assert_eq!(
+            sanitize_path_segment("registry.local/org/app:latest"),
+            "registry.local-org-app-latest"
+        );
     }
 
     #[test]
@@ -717,19 +1806,38 @@ mod tests {
         let root = TempDir::new().expect("tempdir");
         let mount = parse_share("src", root.path()).expect("parse_share failed");
         let expected = root.path().join("src");
-        assert_eq!(mount.source, expected);
-        assert_eq!(mount.target, expected);
-        assert!(!mount.read_only);
-        assert!(mount.options.is_empty());
+        let internal::Mount::Bind {
+            source,
+            target,
+            read_only,
+            options,
+            ..
+        } = mount
+        else {
+            panic!("expected a Bind mount");
+        };
+        assert_eq!(source, expected);
+        assert_eq!(target, expected);
+        assert!(!read_only);
+        assert!(options.is_empty());
     }
 
     #[test]
     fn parse_share_parses_target_and_options() {
         let root = TempDir::new().expect("tempdir");
         let mount = parse_share("src:/dest:ro,z", root.path()).expect("parse_share failed");
-        assert_eq!(mount.source, root.path().join("src"));
-        assert_eq!(mount.target, PathBuf::from("/dest"));
-        assert_eq!(mount.options, vec!["ro".to_string(), "z".to_string()]);
+        let internal::Mount::Bind {
+            source,
+            target,
+            options,
+            ..
+        } = mount
+        else {
+            panic!("expected a Bind mount");
+        };
+        assert_eq!(source, root.path().join("src"));
+        assert_eq!(target, PathBuf::from("/dest"));
+        assert_eq!(options, vec!["ro".to_string(), "z".to_string()]);
     }
 
     #[test]
@@ -813,6 +1921,8 @@ mod tests {
             Some(extra_shell.clone()),
             123,
             456,
+            false,
+            false,
         );
 
         let value = serde_json::to_value(&spec).expect("serialize internal spec");
@@ -831,6 +1941,12 @@ mod tests {
             "extra_shell",
             "prefix_cmd",
             "prefix_cmd_quiet",
+            "pty",
+            "privilege_backend",
+            "rootless",
+            "no_new_privs",
+            "sudo_policy",
+            "env_filter",
         ]
         .into_iter()
         .collect();
@@ -955,6 +2071,8 @@ mod tests {
             None,
             42,
             1000,
+            false,
+            false,
         );
 
         assert_eq!(spec.user.name, "gw-user");
@@ -964,6 +2082,7 @@ mod tests {
             spec.user.home,
             PathBuf::from("/tmp/dr-tmp-home-gw-user/gw-user")
         );
+        assert_eq!(spec.shell, None);
 
         if let Some(value) = prior_user {
             unsafe {
@@ -1011,6 +2130,8 @@ mod tests {
             None,
             0,
             0,
+            false,
+            false,
         );
 
         let persist = spec.persist_env.expect("persist env");
@@ -1020,6 +2141,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn internal_spec_contracts_workdir_to_repo_root_when_opted_in() {
+        if !git_available() {
+            return;
+        }
+
+        let root = TempDir::new().expect("tempdir");
+        let root_dir = root.path().canonicalize().expect("canonicalize root");
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&root_dir)
+            .status()
+            .expect("git init failed");
+        assert!(status.success());
+
+        let nested = root_dir.join("a").join("b");
+        std::fs::create_dir_all(&nested).expect("create nested dir");
+
+        let mut params = HashMap::new();
+        params.insert("workdir_repo_root".to_string(), Vec::new());
+
+        let spec = build_internal_spec(
+            &root_dir,
+            nested.clone(),
+            vec!["true".to_string()],
+            BTreeMap::new(),
+            &params,
+            None,
+            None,
+            0,
+            0,
+            false,
+            false,
+        );
+
+        assert_eq!(spec.workdir, root_dir);
+
+        let spec_without_opt_in = build_internal_spec(
+            &root_dir,
+            nested.clone(),
+            vec!["true".to_string()],
+            BTreeMap::new(),
+            &HashMap::new(),
+            None,
+            None,
+            0,
+            0,
+            false,
+            false,
+        );
+        assert_eq!(spec_without_opt_in.workdir, nested);
+    }
+
+    #[test]
+    fn internal_spec_applies_workdir_substitutions() {
+        let root = TempDir::new().expect("tempdir");
+        let root_dir = root.path().canonicalize().expect("canonicalize root");
+        let workdir = root_dir.join("old-prefix").join("project");
+
+        let mut params = HashMap::new();
+        params.insert(
+            "workdir_substitutions".to_string(),
+            vec!["old-prefix=new-prefix".to_string()],
+        );
+
+        let spec = build_internal_spec(
+            &root_dir,
+            workdir,
+            vec!["true".to_string()],
+            BTreeMap::new(),
+            &params,
+            None,
+            None,
+            0,
+            0,
+            false,
+            false,
+        );
+
+        assert_eq!(spec.workdir, root_dir.join("new-prefix").join("project"));
+    }
+
     #[test]
     fn share_git_dir_skips_repo_inside_root() {
         if !git_available() {
@@ -1034,8 +2237,8 @@ mod tests {
             .expect("git init failed");
         assert!(status.success());
 
-        let mount = share_git_dir(root.path());
-        assert!(mount.is_none());
+        let mounts = share_git_dir(root.path());
+        assert!(mounts.is_empty());
     }
 
     #[test]
@@ -1054,10 +2257,164 @@ mod tests {
             .expect("git init failed");
         assert!(status.success());
 
-        let mount = share_git_dir(root.path()).expect("expected external git dir mount");
-        assert_eq!(mount.source, git_dir.path());
-        assert_eq!(mount.target, git_dir.path());
-        assert!(!mount.read_only);
-        assert!(mount.options.is_empty());
+        let mounts = share_git_dir(root.path());
+        assert_eq!(mounts.len(), 1, "expected a single external git dir mount");
+        let internal::Mount::Bind {
+            source,
+            target,
+            read_only,
+            options,
+            ..
+        } = &mounts[0]
+        else {
+            panic!("expected a Bind mount");
+        };
+        let expected = git_dir.path().canonicalize().expect("canonicalize git dir");
+        assert_eq!(source, &expected);
+        assert_eq!(target, &expected);
+        assert!(!read_only);
+        assert!(options.is_empty());
+    }
+
+    #[test]
+    fn share_git_dir_mounts_worktree_gitdir_and_common_dir() {
+        if !git_available() {
+            return;
+        }
+
+        let main_repo = TempDir::new().expect("tempdir");
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(main_repo.path())
+            .status()
+            .expect("git init failed");
+        assert!(status.success());
+        let commit_status = Command::new("git")
+            .args(["commit", "-q", "--allow-empty", "-m", "init"])
+            .current_dir(main_repo.path())
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .status()
+            .expect("git commit failed");
+        assert!(commit_status.success());
+
+        let worktree = TempDir::new().expect("tempdir");
+        std::fs::remove_dir(worktree.path()).expect("remove placeholder worktree dir");
+        let worktree_status = Command::new("git")
+            .args(["worktree", "add", "-q"])
+            .arg(worktree.path())
+            .current_dir(main_repo.path())
+            .status()
+            .expect("git worktree add failed");
+        assert!(worktree_status.success());
+
+        let mounts = share_git_dir(worktree.path());
+
+        let main_git_dir = main_repo
+            .path()
+            .join(".git")
+            .canonicalize()
+            .expect("canonicalize main .git");
+        let sources: HashSet<PathBuf> = mounts
+            .iter()
+            .map(|mount| {
+                let internal::Mount::Bind { source, .. } = mount else {
+                    panic!("expected a Bind mount");
+                };
+                source.clone()
+            })
+            .collect();
+
+        assert!(
+            sources.contains(&main_git_dir),
+            "expected the main repo's common dir to be mounted, got {sources:?}"
+        );
+        assert!(
+            sources
+                .iter()
+                .any(|source| source.starts_with(&main_git_dir) && source != &main_git_dir),
+            "expected the worktree's private gitdir under the main .git to be mounted, got {sources:?}"
+        );
+    }
+
+    #[test]
+    fn share_git_dir_mounts_submodule_gitdir() {
+        if !git_available() {
+            return;
+        }
+
+        let upstream = TempDir::new().expect("tempdir");
+        let init_status = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(upstream.path())
+            .status()
+            .expect("git init failed");
+        assert!(init_status.success());
+        let commit_status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "init",
+            ])
+            .current_dir(upstream.path())
+            .status()
+            .expect("git commit failed");
+        assert!(commit_status.success());
+
+        let root = TempDir::new().expect("tempdir");
+        let git_dir = TempDir::new().expect("tempdir");
+        let init_separate_status = Command::new("git")
+            .args(["init", "-q", "--separate-git-dir"])
+            .arg(git_dir.path())
+            .current_dir(root.path())
+            .status()
+            .expect("git init failed");
+        assert!(init_separate_status.success());
+
+        let upstream_url = format!("file://{}", upstream.path().display());
+        let submodule_status = Command::new("git")
+            .args([
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "-q",
+                &upstream_url,
+                "sub",
+            ])
+            .current_dir(root.path())
+            .status()
+            .expect("git submodule add failed");
+        assert!(submodule_status.success());
+
+        let mounts = share_git_dir(root.path());
+        let submodule_gitdir = git_dir
+            .path()
+            .join("modules")
+            .join("sub")
+            .canonicalize()
+            .expect("canonicalize submodule gitdir");
+        let sources: HashSet<PathBuf> = mounts
+            .iter()
+            .map(|mount| {
+                let internal::Mount::Bind { source, .. } = mount else {
+                    panic!("expected a Bind mount");
+                };
+                source.clone()
+            })
+            .collect();
+
+        assert!(
+            sources.contains(&submodule_gitdir),
+            "expected the submodule's gitdir to be mounted, got {sources:?}"
+        );
     }
 }
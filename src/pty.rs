@@ -0,0 +1,288 @@
+//! PTY allocation for interactive agent sessions.
+//!
+//! When `InternalSpec::pty` is set, the agent allocates a pseudo-terminal,
+//! makes the shell the session leader on the slave side, and pumps bytes
+//! between the outer stdio and the PTY master, forwarding window-size
+//! changes until the shell exits.
+
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static WINCH_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigwinch(_signum: libc::c_int) {
+    WINCH_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Open a PTY pair, returning the master fd and the path to the slave device.
+fn open_pty() -> Result<(RawFd, PathBuf), String> {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if master < 0 {
+            return Err(format!(
+                "Error: failed to open pty: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        if libc::grantpt(master) != 0 {
+            libc::close(master);
+            return Err(format!(
+                "Error: failed to grant pty: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        if libc::unlockpt(master) != 0 {
+            libc::close(master);
+            return Err(format!(
+                "Error: failed to unlock pty: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let mut buf = vec![0u8; 256];
+        if libc::ptsname_r(master, buf.as_mut_ptr() as *mut libc::c_char, buf.len()) != 0 {
+            libc::close(master);
+            return Err(format!(
+                "Error: failed to resolve pty slave name: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let cname = CStr::from_ptr(buf.as_ptr() as *const libc::c_char);
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(cname.to_bytes()));
+        Ok((master, path))
+    }
+}
+
+fn get_winsize(fd: RawFd) -> Option<libc::winsize> {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws as *mut _) };
+    if rc == 0 { Some(ws) } else { None }
+}
+
+fn set_winsize(fd: RawFd, ws: &libc::winsize) {
+    unsafe {
+        libc::ioctl(fd, libc::TIOCSWINSZ, ws as *const _);
+    }
+}
+
+fn sync_winsize(master_fd: RawFd) {
+    if let Some(ws) = get_winsize(0) {
+        set_winsize(master_fd, &ws);
+    }
+}
+
+struct RawModeGuard {
+    fd: RawFd,
+    original: Option<libc::termios>,
+}
+
+impl RawModeGuard {
+    fn enable(fd: RawFd) -> Self {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Self { fd, original: None };
+        }
+        let mut raw = original;
+        raw.c_iflag &= !(libc::IGNBRK
+            | libc::BRKINT
+            | libc::PARMRK
+            | libc::ISTRIP
+            | libc::INLCR
+            | libc::IGNCR
+            | libc::ICRNL
+            | libc::IXON);
+        raw.c_oflag &= !libc::OPOST;
+        raw.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        raw.c_cflag &= !(libc::CSIZE | libc::PARENB);
+        raw.c_cflag |= libc::CS8;
+        unsafe {
+            libc::tcsetattr(fd, libc::TCSANOW, &raw);
+        }
+        Self {
+            fd,
+            original: Some(original),
+        }
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Some(original) = &self.original {
+            unsafe {
+                libc::tcsetattr(self.fd, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+/// Run `shell -c script` attached to a freshly allocated PTY, pumping bytes
+/// between the outer terminal and the PTY master until the shell exits, and
+/// propagating its exit status via `std::process::exit`.
+pub fn run_in_pty(shell: &str, script: &str, env_map: &BTreeMap<String, String>) -> ! {
+    match run_in_pty_inner(shell, script, env_map) {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_in_pty_inner(
+    shell: &str,
+    script: &str,
+    env_map: &BTreeMap<String, String>,
+) -> Result<i32, String> {
+    let (master_fd, slave_path) = open_pty()?;
+    sync_winsize(master_fd);
+
+    let slave_cpath = std::ffi::CString::new(slave_path.as_os_str().as_bytes())
+        .map_err(|_| "Error: pty slave path contains NUL byte".to_string())?;
+
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        unsafe {
+            libc::close(master_fd);
+        }
+        return Err(format!(
+            "Error: failed to fork for pty: {}",
+            io::Error::last_os_error()
+        ));
+    }
+
+    if pid == 0 {
+        // Child: become session leader, attach the slave as the controlling
+        // terminal, and exec the shell with the slave as all three of its
+        // standard streams.
+        unsafe {
+            libc::close(master_fd);
+            libc::setsid();
+            let slave_fd = libc::open(slave_cpath.as_ptr(), libc::O_RDWR);
+            if slave_fd < 0 {
+                std::process::exit(127);
+            }
+            libc::ioctl(slave_fd, libc::TIOCSCTTY as libc::c_ulong, 0);
+            libc::dup2(slave_fd, 0);
+            libc::dup2(slave_fd, 1);
+            libc::dup2(slave_fd, 2);
+            if slave_fd > 2 {
+                libc::close(slave_fd);
+            }
+        }
+        let err = std::os::unix::process::CommandExt::exec(
+            Command::new(shell).arg("-c").arg(script).env_clear().envs(env_map),
+        );
+        eprintln!("Error: failed to exec shell in pty: {err}");
+        std::process::exit(127);
+    }
+
+    // Parent: relay bytes and forward window-size changes until the child
+    // exits.
+    let _raw_guard = RawModeGuard::enable(0);
+    unsafe {
+        libc::signal(libc::SIGWINCH, handle_sigwinch as libc::sighandler_t);
+    }
+
+    let exit_code = pump_and_wait(master_fd, pid)?;
+    unsafe {
+        libc::close(master_fd);
+    }
+    Ok(exit_code)
+}
+
+fn pump_and_wait(master_fd: RawFd, child: libc::pid_t) -> Result<i32, String> {
+    let mut buf = [0u8; 4096];
+    loop {
+        if WINCH_RECEIVED.swap(false, Ordering::SeqCst) {
+            sync_winsize(master_fd);
+        }
+
+        let mut read_fds: libc::fd_set = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::FD_ZERO(&mut read_fds);
+            libc::FD_SET(0, &mut read_fds);
+            libc::FD_SET(master_fd, &mut read_fds);
+        }
+        let nfds = master_fd + 1;
+        let mut timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 200_000,
+        };
+        let ready = unsafe {
+            libc::select(
+                nfds,
+                &mut read_fds,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut timeout,
+            )
+        };
+
+        if ready > 0 {
+            if unsafe { libc::FD_ISSET(0, &read_fds) } {
+                let n = unsafe { libc::read(0, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if n > 0 {
+                    unsafe {
+                        libc::write(master_fd, buf.as_ptr() as *const libc::c_void, n as usize);
+                    }
+                }
+            }
+            if unsafe { libc::FD_ISSET(master_fd, &read_fds) } {
+                let n = unsafe {
+                    libc::read(master_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                };
+                if n > 0 {
+                    unsafe {
+                        libc::write(1, buf.as_ptr() as *const libc::c_void, n as usize);
+                    }
+                } else if n == 0 {
+                    break;
+                }
+            }
+        }
+
+        let mut status: libc::c_int = 0;
+        let waited = unsafe { libc::waitpid(child, &mut status, libc::WNOHANG) };
+        if waited == child {
+            return Ok(exit_code_from_status(status));
+        }
+    }
+
+    let mut status: libc::c_int = 0;
+    unsafe {
+        libc::waitpid(child, &mut status, 0);
+    }
+    Ok(exit_code_from_status(status))
+}
+
+fn exit_code_from_status(status: libc::c_int) -> i32 {
+    unsafe {
+        if libc::WIFEXITED(status) {
+            libc::WEXITSTATUS(status)
+        } else {
+            128 + libc::WTERMSIG(status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exit_code_from_status;
+
+    #[test]
+    fn exit_code_from_status_reads_normal_exit() {
+        let status = 42 << 8;
+        assert_eq!(exit_code_from_status(status), 42);
+    }
+
+    #[test]
+    fn exit_code_from_status_maps_signal_to_128_plus_signum() {
+        let status = libc::SIGKILL;
+        assert_eq!(exit_code_from_status(status), 128 + libc::SIGKILL);
+    }
+}
@@ -0,0 +1,876 @@
+//! Pluggable container-runtime backends (podman/docker/nerdctl).
+//!
+//! `Backend::build_run_args`/`build_build_args` translate a `ContainerSpec`
+//! into the argv for a specific runtime binary, isolating the small
+//! per-runtime flag differences (e.g. how `--privileged` is spelled)
+//! instead of baking Podman assumptions into `ContainerSpec` itself.
+
+use std::fmt;
+
+use crate::internal::{BuildSpec, ContainerSpec, Mount};
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    message: String,
+}
+
+impl RuntimeError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A container runtime CLI (podman/docker/nerdctl) capable of translating a
+/// `ContainerSpec` into argv for `run`/`build`.
+pub trait Backend: fmt::Debug {
+    /// The binary to exec, e.g. `"podman"`.
+    fn binary_name(&self) -> &'static str;
+
+    /// Build the `run` subcommand argv (without the binary name itself).
+    fn build_run_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError>;
+
+    /// Build the `create` subcommand argv (without the binary name itself) -
+    /// the same flags `build_run_args` emits, but leaving the container
+    /// stopped instead of starting it, for the lifecycle-managed path
+    /// (`exec::create`/`start`/`stop`/...) as opposed to `run`'s one-shot
+    /// foreground flow.
+    fn build_create_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError>;
+
+    /// Build the `build` subcommand argv (without the binary name itself).
+    /// Identical across podman/docker/nerdctl, so it has a shared default.
+    fn build_build_args(&self, spec: &BuildSpec) -> Vec<String> {
+        let mut args = vec!["build".to_string()];
+        if let Some(dockerfile) = &spec.dockerfile {
+            args.push("-f".to_string());
+            args.push(dockerfile.to_string_lossy().into_owned());
+        }
+        for (key, value) in &spec.build_args {
+            args.push("--build-arg".to_string());
+            args.push(format!("{key}={value}"));
+        }
+        args.push("-t".to_string());
+        args.push(spec.image.clone());
+        args.push(spec.context_dir.to_string_lossy().into_owned());
+        args
+    }
+
+    /// Flags telling the runtime to keep `count` fds beyond stdio open and
+    /// pass them through to the container (used to forward a legacy
+    /// fd-style `make` jobserver). Only Podman supports this today, so the
+    /// default is a no-op.
+    fn preserve_fds_args(&self, count: u32) -> Vec<String> {
+        let _ = count;
+        Vec::new()
+    }
+
+    /// Build the `image exists` check argv (without the binary name) used by
+    /// `exec::image_exists`. Podman has a dedicated `image exists`
+    /// subcommand (exit 0 present / 1 absent); Docker/nerdctl don't ship
+    /// that verb, so the default falls back to `image inspect`, which exits
+    /// the same way for a missing image and discards its (unused) stdout.
+    fn build_image_exists_args(&self, image: &str) -> Vec<String> {
+        vec![
+            "image".to_string(),
+            "inspect".to_string(),
+            image.to_string(),
+        ]
+    }
+}
+
+/// Resolve a `gw_runtime`/`--gw-runtime=` value to its `Backend`. Unknown
+/// names are rejected rather than silently falling back to Podman, so a
+/// typo surfaces immediately instead of quietly invoking the wrong binary.
+pub fn from_name(name: &str) -> Result<Box<dyn Backend>, RuntimeError> {
+    match name {
+        "podman" => Ok(Box::new(Podman)),
+        "docker" => Ok(Box::new(Docker)),
+        "nerdctl" => Ok(Box::new(Nerdctl)),
+        other => Err(RuntimeError::new(format!(
+            "Error: unknown gw_runtime \"{other}\" (expected podman, docker, or nerdctl)"
+        ))),
+    }
+}
+
+/// Pick a default backend when neither `--gw-runtime=` nor `gw_runtime` says
+/// otherwise: honor `$GW_RUNTIME` if it names a known backend, else probe
+/// `$PATH` in Podman/Docker/nerdctl preference order, else fall back to
+/// Podman for backward compatibility. A bare binary-on-`runc` check isn't
+/// offered here - `runc` takes an OCI bundle rather than `run`-style flags,
+/// so it doesn't fit the `ContainerSpec` -> argv translation the other three
+/// backends share and isn't one of `from_name`'s targets.
+pub fn detect_default() -> Box<dyn Backend> {
+    if let Ok(name) = std::env::var("GW_RUNTIME") {
+        if let Ok(backend) = from_name(&name) {
+            return backend;
+        }
+    }
+    for name in ["podman", "docker", "nerdctl"] {
+        if binary_on_path(name) {
+            return from_name(name).expect("name is one of from_name's known backends");
+        }
+    }
+    Box::new(Podman)
+}
+
+fn binary_on_path(name: &str) -> bool {
+    let Some(path_var) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path_var).any(|dir| dir.join(name).is_file())
+}
+
+#[derive(Debug)]
+pub struct Podman;
+
+#[derive(Debug)]
+pub struct Docker;
+
+#[derive(Debug)]
+pub struct Nerdctl;
+
+/// How a backend spells `--privileged` when enabled: Podman wants an
+/// explicit `=true`, Docker/nerdctl take it as a bare flag.
+enum PrivilegedSpelling {
+    ExplicitTrue,
+    Bare,
+}
+
+impl Backend for Podman {
+    fn binary_name(&self) -> &'static str {
+        "podman"
+    }
+
+    fn build_run_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "run", spec, PrivilegedSpelling::ExplicitTrue)
+    }
+
+    fn build_create_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "create", spec, PrivilegedSpelling::ExplicitTrue)
+    }
+
+    fn preserve_fds_args(&self, count: u32) -> Vec<String> {
+        if count > 0 {
+            vec![format!("--preserve-fds={count}")]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn build_image_exists_args(&self, image: &str) -> Vec<String> {
+        vec!["image".to_string(), "exists".to_string(), image.to_string()]
+    }
+}
+
+impl Backend for Docker {
+    fn binary_name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn build_run_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "run", spec, PrivilegedSpelling::Bare)
+    }
+
+    fn build_create_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "create", spec, PrivilegedSpelling::Bare)
+    }
+}
+
+impl Backend for Nerdctl {
+    fn binary_name(&self) -> &'static str {
+        "nerdctl"
+    }
+
+    fn build_run_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "run", spec, PrivilegedSpelling::Bare)
+    }
+
+    fn build_create_args(&self, spec: &ContainerSpec) -> Result<Vec<String>, RuntimeError> {
+        build_verb_args(self, "create", spec, PrivilegedSpelling::Bare)
+    }
+}
+
+/// `build_run_args`/`build_create_args` share every flag - only the leading
+/// verb differs (`run` starts the container immediately, `create` leaves it
+/// stopped for the lifecycle-managed path to `start` later).
+fn build_verb_args(
+    backend: &dyn Backend,
+    verb: &str,
+    spec: &ContainerSpec,
+    privileged_spelling: PrivilegedSpelling,
+) -> Result<Vec<String>, RuntimeError> {
+    let mut args = vec![verb.to_string()];
+    args.extend(container_flags(backend, spec, privileged_spelling)?);
+    Ok(args)
+}
+
+/// The flags and trailing image/command argv shared by `run` and `create` -
+/// everything except the leading verb.
+fn container_flags(
+    backend: &dyn Backend,
+    spec: &ContainerSpec,
+    privileged_spelling: PrivilegedSpelling,
+) -> Result<Vec<String>, RuntimeError> {
+    let mut args = Vec::new();
+
+    if spec.interactive {
+        args.push("-i".to_string());
+    }
+    if spec.tty {
+        args.push("-t".to_string());
+    }
+
+    if spec.remove {
+        args.push("--rm".to_string());
+    }
+
+    if spec.init {
+        args.push("--init".to_string());
+    }
+    if spec.privileged {
+        match privileged_spelling {
+            PrivilegedSpelling::ExplicitTrue => args.push("--privileged=true".to_string()),
+            PrivilegedSpelling::Bare => args.push("--privileged".to_string()),
+        }
+    } else {
+        for cap in &spec.cap_drop {
+            args.push("--cap-drop".to_string());
+            args.push(cap.clone());
+        }
+        for cap in &spec.cap_add {
+            args.push("--cap-add".to_string());
+            args.push(cap.clone());
+        }
+        if spec.no_new_privileges {
+            args.push("--security-opt".to_string());
+            args.push("no-new-privileges".to_string());
+        }
+    }
+    if let Some(userns) = &spec.userns {
+        args.push(format!("--userns={userns}"));
+    }
+
+    args.extend(backend.preserve_fds_args(spec.preserve_fds));
+
+    if let Some(mem_limit) = &spec.mem_limit {
+        args.push("--memory".to_string());
+        args.push(mem_limit.clone());
+    }
+    if let Some(cpu_limit) = &spec.cpu_limit {
+        args.push("--cpus".to_string());
+        args.push(cpu_limit.clone());
+    }
+    if let Some(pids_limit) = &spec.pids_limit {
+        args.push("--pids-limit".to_string());
+        args.push(pids_limit.clone());
+    }
+
+    if let Some(hostname) = &spec.hostname {
+        args.push("-h".to_string());
+        args.push(hostname.clone());
+    }
+
+    for host in &spec.extra_hosts {
+        args.push("--add-host".to_string());
+        args.push(host.clone());
+    }
+
+    for mount in &spec.mounts {
+        args.extend(mount_args(mount));
+    }
+
+    for (key, value) in &spec.env {
+        args.push("--env".to_string());
+        args.push(format!("{key}={value}"));
+    }
+
+    if let Some(workdir) = &spec.workdir {
+        args.push("-w".to_string());
+        args.push(workdir.to_string_lossy().into_owned());
+    }
+
+    if let Some(user) = &spec.user {
+        args.push("-u".to_string());
+        args.push(user.clone());
+    }
+
+    if let Some(entrypoint) = &spec.entrypoint {
+        match entrypoint.as_slice() {
+            [] => {}
+            [single] => {
+                args.push("--entrypoint".to_string());
+                args.push(single.clone());
+            }
+            _ => {
+                return Err(RuntimeError::new(
+                    "Error: entrypoint must be a single argv element",
+                ));
+            }
+        }
+    }
+
+    for extra in &spec.extra_args {
+        args.push(extra.clone());
+    }
+
+    args.push(spec.image.clone());
+    args.extend(spec.command.iter().cloned());
+
+    Ok(args)
+}
+
+/// Translate one `Mount` into the flag(s) and value that express it on the
+/// runtime's command line - `-v` for binds and named volumes, `--tmpfs` for
+/// ephemeral scratch space.
+fn mount_args(mount: &Mount) -> Vec<String> {
+    match mount {
+        Mount::Bind {
+            source,
+            target,
+            read_only,
+            propagation,
+            selinux_relabel,
+            options,
+        } => {
+            let mut opts: Vec<String> = options
+                .iter()
+                .filter(|opt| !opt.is_empty())
+                .cloned()
+                .collect();
+            if let Some(propagation) = propagation {
+                push_option_once(&mut opts, propagation.as_flag());
+            }
+            if let Some(relabel) = selinux_relabel {
+                push_option_once(&mut opts, relabel.as_flag());
+            }
+            if *read_only {
+                push_option_once(&mut opts, "ro");
+            }
+
+            let mut arg = format!("{}:{}", source.to_string_lossy(), target.to_string_lossy());
+            if !opts.is_empty() {
+                arg.push(':');
+                arg.push_str(&opts.join(","));
+            }
+            vec!["-v".to_string(), arg]
+        }
+        Mount::Tmpfs { target, size, mode } => {
+            let mut opts = Vec::new();
+            if let Some(size) = size {
+                opts.push(format!("size={size}"));
+            }
+            if let Some(mode) = mode {
+                opts.push(format!("mode={mode}"));
+            }
+            let mut arg = target.to_string_lossy().into_owned();
+            if !opts.is_empty() {
+                arg.push(':');
+                arg.push_str(&opts.join(","));
+            }
+            vec!["--tmpfs".to_string(), arg]
+        }
+        Mount::Volume {
+            name,
+            target,
+            read_only,
+        } => {
+            let mut arg = format!("{name}:{}", target.to_string_lossy());
+            if *read_only {
+                arg.push_str(":ro");
+            }
+            vec!["-v".to_string(), arg]
+        }
+    }
+}
+
+/// Push `option` onto `options` unless it's already present, so repeatedly
+/// deriving the same flag (e.g. from both `read_only` and an explicit `ro` in
+/// `options`) doesn't duplicate it - the same idempotency `ro` already had.
+fn push_option_once(options: &mut Vec<String>, option: &str) {
+    if !options.iter().any(|opt| opt == option) {
+        options.push(option.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use super::{from_name, Backend, Docker, Nerdctl, Podman};
+    use crate::internal::{BuildSpec, ContainerSpec, Mount, MountPropagation, SelinuxRelabel};
+
+    fn base_spec() -> ContainerSpec {
+        ContainerSpec {
+            image: "example:latest".to_string(),
+            hostname: None,
+            mounts: Vec::new(),
+            env: BTreeMap::new(),
+            workdir: None,
+            user: None,
+            extra_hosts: Vec::new(),
+            userns: None,
+            privileged: false,
+            cap_drop: Vec::new(),
+            cap_add: Vec::new(),
+            no_new_privileges: false,
+            init: false,
+            remove: false,
+            interactive: false,
+            tty: false,
+            entrypoint: None,
+            command: Vec::new(),
+            extra_args: Vec::new(),
+            preserve_fds: 0,
+            mem_limit: None,
+            cpu_limit: None,
+            pids_limit: None,
+        }
+    }
+
+    #[test]
+    fn podman_build_run_args_orders_flags_and_values() {
+        let mut spec = base_spec();
+        spec.image = "registry/app:tag".to_string();
+        spec.interactive = true;
+        spec.tty = true;
+        spec.remove = true;
+        spec.init = true;
+        spec.privileged = true;
+        spec.hostname = Some("gw-host".to_string());
+        spec.extra_hosts = vec![
+            "host.docker.internal:host-gateway".to_string(),
+            "db:10.0.0.2".to_string(),
+        ];
+        spec.mounts = vec![
+            Mount::Bind {
+                source: PathBuf::from("/src"),
+                target: PathBuf::from("/workspace"),
+                read_only: false,
+                propagation: None,
+                selinux_relabel: Some(SelinuxRelabel::Shared),
+                options: Vec::new(),
+            },
+            Mount::Bind {
+                source: PathBuf::from("/data"),
+                target: PathBuf::from("/data"),
+                read_only: true,
+                propagation: None,
+                selinux_relabel: Some(SelinuxRelabel::Private),
+                options: Vec::new(),
+            },
+        ];
+        spec.env.insert("B".to_string(), "2".to_string());
+        spec.env.insert("A".to_string(), "1".to_string());
+        spec.workdir = Some(PathBuf::from("/work"));
+        spec.user = Some("1000:1000".to_string());
+        spec.entrypoint = Some(vec!["/bin/sh".to_string()]);
+        spec.extra_args = vec![
+            "--security-opt=label=disable".to_string(),
+            "--pids-limit=100".to_string(),
+        ];
+        spec.command = vec!["bash".to_string(), "-lc".to_string(), "true".to_string()];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "-i",
+                "-t",
+                "--rm",
+                "--init",
+                "--privileged=true",
+                "-h",
+                "gw-host",
+                "--add-host",
+                "host.docker.internal:host-gateway",
+                "--add-host",
+                "db:10.0.0.2",
+                "-v",
+                "/src:/workspace:z",
+                "-v",
+                "/data:/data:Z,ro",
+                "--env",
+                "A=1",
+                "--env",
+                "B=2",
+                "-w",
+                "/work",
+                "-u",
+                "1000:1000",
+                "--entrypoint",
+                "/bin/sh",
+                "--security-opt=label=disable",
+                "--pids-limit=100",
+                "registry/app:tag",
+                "bash",
+                "-lc",
+                "true",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_create_args_shares_flags_with_run_but_not_the_verb() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.remove = true;
+
+        let run_args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        let create_args = Podman
+            .build_create_args(&spec)
+            .expect("build_create_args failed");
+        assert_eq!(run_args[0], "run");
+        assert_eq!(create_args[0], "create");
+        assert_eq!(run_args[1..], create_args[1..]);
+    }
+
+    #[test]
+    fn build_run_args_skips_empty_entrypoint() {
+        let mut spec = base_spec();
+        spec.entrypoint = Some(Vec::new());
+        spec.image = "busybox".to_string();
+        spec.command = vec!["echo".to_string(), "ok".to_string()];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "busybox", "echo", "ok"]);
+    }
+
+    #[test]
+    fn build_run_args_rejects_multi_element_entrypoint() {
+        let mut spec = base_spec();
+        spec.entrypoint = Some(vec!["/bin/sh".to_string(), "-c".to_string()]);
+
+        let err = Podman
+            .build_run_args(&spec)
+            .err()
+            .expect("expected build_run_args to fail");
+        assert_eq!(
+            err.to_string(),
+            "Error: entrypoint must be a single argv element"
+        );
+    }
+
+    #[test]
+    fn build_run_args_keeps_ro_option_once() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.mounts = vec![Mount::Bind {
+            source: PathBuf::from("/src"),
+            target: PathBuf::from("/dest"),
+            read_only: true,
+            propagation: None,
+            selinux_relabel: Some(SelinuxRelabel::Private),
+            options: vec!["ro".to_string()],
+        }];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "-v", "/src:/dest:ro,Z", "busybox"]);
+    }
+
+    #[test]
+    fn build_run_args_emits_tmpfs_mount() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.mounts = vec![Mount::Tmpfs {
+            target: PathBuf::from("/tmp/scratch"),
+            size: Some("100m".to_string()),
+            mode: Some("1777".to_string()),
+        }];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "--tmpfs",
+                "/tmp/scratch:size=100m,mode=1777",
+                "busybox"
+            ]
+        );
+    }
+
+    #[test]
+    fn build_run_args_emits_named_volume() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.mounts = vec![Mount::Volume {
+            name: "gw-home".to_string(),
+            target: PathBuf::from("/home/dev"),
+            read_only: false,
+        }];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "-v", "gw-home:/home/dev", "busybox"]);
+    }
+
+    #[test]
+    fn build_run_args_applies_bind_propagation_once() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.mounts = vec![Mount::Bind {
+            source: PathBuf::from("/src"),
+            target: PathBuf::from("/dest"),
+            read_only: false,
+            propagation: Some(MountPropagation::RShared),
+            selinux_relabel: None,
+            options: vec!["rshared".to_string()],
+        }];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "-v", "/src:/dest:rshared", "busybox"]);
+    }
+
+    #[test]
+    fn build_run_args_emits_userns_when_rootless() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.userns = Some("keep-id:uid=1000,gid=1000".to_string());
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(
+            args,
+            vec!["run", "--userns=keep-id:uid=1000,gid=1000", "busybox"]
+        );
+    }
+
+    #[test]
+    fn build_run_args_emits_resource_limits() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.mem_limit = Some("2g".to_string());
+        spec.cpu_limit = Some("2.5".to_string());
+        spec.pids_limit = Some("256".to_string());
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "--memory",
+                "2g",
+                "--cpus",
+                "2.5",
+                "--pids-limit",
+                "256",
+                "busybox",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_run_args_emits_cap_add_and_cap_drop_when_not_privileged() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.cap_drop = vec!["ALL".to_string()];
+        spec.cap_add = vec!["CHOWN".to_string(), "SETUID".to_string()];
+        spec.no_new_privileges = true;
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(
+            args,
+            vec![
+                "run",
+                "--cap-drop",
+                "ALL",
+                "--cap-add",
+                "CHOWN",
+                "--cap-add",
+                "SETUID",
+                "--security-opt",
+                "no-new-privileges",
+                "busybox",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_run_args_omits_no_new_privileges_by_default() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.cap_drop = vec!["ALL".to_string()];
+        spec.cap_add = vec!["CHOWN".to_string(), "SETUID".to_string()];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert!(
+            !args.iter().any(|arg| arg == "no-new-privileges"),
+            "no-new-privileges must stay opt-in so it doesn't neuter the default sudoers grant: {args:?}"
+        );
+    }
+
+    #[test]
+    fn build_run_args_skips_cap_flags_when_privileged() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.privileged = true;
+        spec.cap_drop = vec!["ALL".to_string()];
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "--privileged=true", "busybox"]);
+    }
+
+    #[test]
+    fn podman_emits_preserve_fds_when_forwarding_a_jobserver() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.preserve_fds = 2;
+
+        let args = Podman.build_run_args(&spec).expect("build_run_args failed");
+        assert_eq!(args, vec!["run", "--preserve-fds=2", "busybox"]);
+    }
+
+    #[test]
+    fn docker_and_nerdctl_ignore_preserve_fds() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.preserve_fds = 2;
+
+        assert_eq!(
+            Docker.build_run_args(&spec).unwrap(),
+            vec!["run", "busybox"]
+        );
+        assert_eq!(
+            Nerdctl.build_run_args(&spec).unwrap(),
+            vec!["run", "busybox"]
+        );
+    }
+
+    #[test]
+    fn docker_and_nerdctl_spell_privileged_as_a_bare_flag() {
+        let mut spec = base_spec();
+        spec.image = "busybox".to_string();
+        spec.privileged = true;
+
+        assert!(Docker
+            .build_run_args(&spec)
+            .unwrap()
+            .contains(&"--privileged".to_string()));
+        assert!(Nerdctl
+            .build_run_args(&spec)
+            .unwrap()
+            .contains(&"--privileged".to_string()));
+    }
+
+    #[test]
+    fn build_build_args_includes_dockerfile_and_build_args() {
+        let mut build_args = BTreeMap::new();
+        build_args.insert("UID".to_string(), "1000".to_string());
+        build_args.insert("PKGS".to_string(), "git curl".to_string());
+        let spec = BuildSpec {
+            image: "registry/app:tag".to_string(),
+            context_dir: PathBuf::from("/ctx"),
+            dockerfile: Some(PathBuf::from("/tmp/gw-containerfile")),
+            build_args,
+        };
+
+        let args = Podman.build_build_args(&spec);
+        assert_eq!(
+            args,
+            vec![
+                "build",
+                "-f",
+                "/tmp/gw-containerfile",
+                "--build-arg",
+                "PKGS=git curl",
+                "--build-arg",
+                "UID=1000",
+                "-t",
+                "registry/app:tag",
+                "/ctx",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_build_args_omits_dockerfile_flag_when_untemplated() {
+        let spec = BuildSpec {
+            image: "busybox".to_string(),
+            context_dir: PathBuf::from("/ctx"),
+            dockerfile: None,
+            build_args: BTreeMap::new(),
+        };
+
+        let args = Podman.build_build_args(&spec);
+        assert_eq!(args, vec!["build", "-t", "busybox", "/ctx"]);
+    }
+
+    #[test]
+    fn podman_uses_dedicated_image_exists_subcommand() {
+        assert_eq!(
+            Podman.build_image_exists_args("app:tag"),
+            vec!["image", "exists", "app:tag"]
+        );
+    }
+
+    #[test]
+    fn docker_and_nerdctl_fall_back_to_image_inspect() {
+        assert_eq!(
+            Docker.build_image_exists_args("app:tag"),
+            vec!["image", "inspect", "app:tag"]
+        );
+        assert_eq!(
+            Nerdctl.build_image_exists_args("app:tag"),
+            vec!["image", "inspect", "app:tag"]
+        );
+    }
+
+    #[test]
+    fn from_name_resolves_known_backends() {
+        assert_eq!(from_name("podman").unwrap().binary_name(), "podman");
+        assert_eq!(from_name("docker").unwrap().binary_name(), "docker");
+        assert_eq!(from_name("nerdctl").unwrap().binary_name(), "nerdctl");
+    }
+
+    #[test]
+    fn detect_default_honors_gw_runtime_env_var() {
+        let prior = std::env::var("GW_RUNTIME").ok();
+        unsafe {
+            std::env::set_var("GW_RUNTIME", "docker");
+        }
+        let backend = super::detect_default();
+        unsafe {
+            match &prior {
+                Some(value) => std::env::set_var("GW_RUNTIME", value),
+                None => std::env::remove_var("GW_RUNTIME"),
+            }
+        }
+        assert_eq!(backend.binary_name(), "docker");
+    }
+
+    #[test]
+    fn detect_default_ignores_unknown_gw_runtime_env_var() {
+        let prior = std::env::var("GW_RUNTIME").ok();
+        unsafe {
+            std::env::set_var("GW_RUNTIME", "containerd-shim");
+        }
+        // Falls through to PATH probing (or the Podman fallback) instead of
+        // erroring, since detect_default has no Result to report one in.
+        let backend = super::detect_default();
+        unsafe {
+            match &prior {
+                Some(value) => std::env::set_var("GW_RUNTIME", value),
+                None => std::env::remove_var("GW_RUNTIME"),
+            }
+        }
+        assert_ne!(backend.binary_name(), "containerd-shim");
+    }
+
+    #[test]
+    fn from_name_rejects_unknown_runtime() {
+        let err = from_name("containerd-shim").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error: unknown gw_runtime \"containerd-shim\" (expected podman, docker, or nerdctl)"
+        );
+    }
+}
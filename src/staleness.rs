@@ -0,0 +1,341 @@
+//! Content-addressed staleness detection for `rebuild_plan`: decide whether
+//! the image needs rebuilding even without an explicit `--gw-rebuild`, by
+//! hashing the Containerfile/Dockerfile plus a configurable `build_inputs`
+//! glob list into a single blake3 digest and comparing it against the
+//! digest stamped under `target/` the last time this image was built.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+#[derive(Debug)]
+pub struct StalenessError {
+    message: String,
+}
+
+impl StalenessError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for StalenessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for StalenessError {}
+
+/// Collect the files a rebuild decision should be hashed against: the
+/// Containerfile/Dockerfile (if any), plus everything `build_inputs`
+/// selects - each entry either a glob (`*`/`**`) matched against every file
+/// under `root_dir`, or a make-style `.d` dep file whose own listed
+/// dependencies are added instead of the `.d` file itself. Returned sorted
+/// and deduplicated so `compute_digest` is insensitive to input order.
+pub fn collect_build_inputs(
+    root_dir: &Path,
+    containerfile: Option<&Path>,
+    build_inputs: &[String],
+) -> Result<Vec<PathBuf>, StalenessError> {
+    let mut selected = BTreeSet::new();
+    if let Some(containerfile) = containerfile {
+        if containerfile.is_file() {
+            selected.insert(containerfile.to_path_buf());
+        }
+    }
+
+    if !build_inputs.is_empty() {
+        let mut all_files = None;
+        for entry in build_inputs {
+            if entry.ends_with(".d") {
+                let dep_file = root_dir.join(entry);
+                for dep in parse_dep_file(&dep_file)? {
+                    let resolved = if Path::new(&dep).is_absolute() {
+                        PathBuf::from(&dep)
+                    } else {
+                        root_dir.join(&dep)
+                    };
+                    if resolved.is_file() {
+                        selected.insert(resolved);
+                    }
+                }
+                continue;
+            }
+
+            let files = match &all_files {
+                Some(files) => files,
+                None => {
+                    all_files = Some(walk_all_files(root_dir)?);
+                    all_files.as_ref().expect("just set")
+                }
+            };
+            let regex = glob_to_regex(entry)?;
+            for rel in files {
+                if regex.is_match(rel) {
+                    selected.insert(root_dir.join(rel));
+                }
+            }
+        }
+    }
+
+    Ok(selected.into_iter().collect())
+}
+
+/// Translate a glob (`*` matches any run of non-`/` characters, `**`
+/// matches any run of characters including `/`, and `**/` matches zero or
+/// more whole directories) into an anchored regex matched against a
+/// `/`-joined path relative to `root_dir`.
+fn glob_to_regex(glob: &str) -> Result<Regex, StalenessError> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        pattern.push_str("(?:.*/)?");
+                    } else {
+                        pattern.push_str(".*");
+                    }
+                } else {
+                    pattern.push_str("[^/]*");
+                }
+            }
+            '.' | '(' | ')' | '+' | '^' | '$' | '|' | '[' | ']' | '{' | '}' | '\\' => {
+                pattern.push('\\');
+                pattern.push(ch);
+            }
+            other => pattern.push(other),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|err| {
+        StalenessError::new(format!("Error: invalid build_inputs glob {glob:?}: {err}"))
+    })
+}
+
+/// Every regular file under `context_dir`, as absolute paths - the full set
+/// `build_image_if_stale` hashes to decide whether a build context has
+/// changed, as opposed to `collect_build_inputs`'s configurable glob subset.
+pub fn collect_context_files(context_dir: &Path) -> Result<Vec<PathBuf>, StalenessError> {
+    let relative = walk_all_files(context_dir)?;
+    Ok(relative.into_iter().map(|rel| context_dir.join(rel)).collect())
+}
+
+/// Every regular file under `root_dir`, as `/`-joined paths relative to it -
+/// walked once per `collect_build_inputs` call and matched against every
+/// glob entry, rather than re-walking per pattern.
+fn walk_all_files(root_dir: &Path) -> Result<Vec<String>, StalenessError> {
+    let mut files = Vec::new();
+    walk_all_files_inner(root_dir, root_dir, &mut files)?;
+    Ok(files)
+}
+
+fn walk_all_files_inner(
+    root_dir: &Path,
+    dir: &Path,
+    files: &mut Vec<String>,
+) -> Result<(), StalenessError> {
+    let entries = fs::read_dir(dir).map_err(|err| {
+        StalenessError::new(format!(
+            "Error: failed to read directory {}: {err}",
+            dir.display()
+        ))
+    })?;
+    for entry in entries {
+        let entry = entry.map_err(|err| {
+            StalenessError::new(format!(
+                "Error: failed to read directory entry {}: {err}",
+                dir.display()
+            ))
+        })?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|err| {
+            StalenessError::new(format!(
+                "Error: failed to read entry type {}: {err}",
+                path.display()
+            ))
+        })?;
+        if file_type.is_dir() {
+            walk_all_files_inner(root_dir, &path, files)?;
+        } else if file_type.is_file() {
+            let rel = path.strip_prefix(root_dir).unwrap_or(&path);
+            files.push(rel.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/"));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a make-style `.d` dep file (`target: dep1 dep2 \` continuation
+/// lines) into its listed dependency paths, ignoring the target itself.
+fn parse_dep_file(path: &Path) -> Result<Vec<String>, StalenessError> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Ok(Vec::new());
+    };
+    let joined = contents.replace("\\\n", " ").replace("\\\r\n", " ");
+    let Some((_target, deps)) = joined.split_once(':') else {
+        return Ok(Vec::new());
+    };
+    Ok(deps.split_whitespace().map(str::to_string).collect())
+}
+
+/// blake3 digest of `inputs`' relative paths and contents, framed so the
+/// digest is bound to both the file set and its bytes - a renamed file
+/// can't coincide with a different set's digest via naive concatenation.
+pub fn compute_digest(root_dir: &Path, inputs: &[PathBuf]) -> Result<String, StalenessError> {
+    let mut sorted = inputs.to_vec();
+    sorted.sort();
+    sorted.dedup();
+
+    let mut hasher = blake3::Hasher::new();
+    for path in &sorted {
+        let rel = path.strip_prefix(root_dir).unwrap_or(path);
+        hasher.update(rel.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+        let contents = fs::read(path).map_err(|err| {
+            StalenessError::new(format!("Error: failed to read {}: {err}", path.display()))
+        })?;
+        hasher.update(&contents);
+        hasher.update(b"\0");
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Read the digest stamped at `marker` by a previous `write_marker`, or
+/// `None` if it doesn't exist yet.
+pub fn read_marker(marker: &Path) -> Option<String> {
+    fs::read_to_string(marker)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+/// Stamp `digest` at `marker`, creating its parent directory if needed.
+pub fn write_marker(marker: &Path, digest: &str) -> Result<(), StalenessError> {
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            StalenessError::new(format!(
+                "Error: failed to create {}: {err}",
+                parent.display()
+            ))
+        })?;
+    }
+    fs::write(marker, digest).map_err(|err| {
+        StalenessError::new(format!(
+            "Error: failed to write staleness marker {}: {err}",
+            marker.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collect_build_inputs, collect_context_files, compute_digest, glob_to_regex, parse_dep_file,
+        read_marker, write_marker,
+    };
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn glob_to_regex_matches_double_star_across_directories() {
+        let regex = glob_to_regex("src/**/*.rs").expect("glob_to_regex");
+        assert!(regex.is_match("src/a/b/main.rs"));
+        assert!(regex.is_match("src/main.rs"));
+        assert!(!regex.is_match("src/main.txt"));
+    }
+
+    #[test]
+    fn glob_to_regex_single_star_stops_at_slash() {
+        let regex = glob_to_regex("src/*.rs").expect("glob_to_regex");
+        assert!(regex.is_match("src/main.rs"));
+        assert!(!regex.is_match("src/a/main.rs"));
+    }
+
+    #[test]
+    fn collect_build_inputs_matches_globs_and_containerfile() {
+        let root = TempDir::new().expect("tempdir");
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.path().join("Containerfile"), "FROM scratch").unwrap();
+        fs::write(root.path().join("README.md"), "ignored").unwrap();
+
+        let containerfile = root.path().join("Containerfile");
+        let inputs = collect_build_inputs(
+            root.path(),
+            Some(&containerfile),
+            &["src/**/*.rs".to_string()],
+        )
+        .expect("collect_build_inputs");
+
+        assert_eq!(
+            inputs,
+            vec![containerfile, root.path().join("src/main.rs")]
+        );
+    }
+
+    #[test]
+    fn collect_context_files_finds_every_file_regardless_of_extension() {
+        let root = TempDir::new().expect("tempdir");
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.path().join("README.md"), "not ignored here").unwrap();
+
+        let mut files = collect_context_files(root.path()).expect("collect_context_files");
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                root.path().join("README.md"),
+                root.path().join("src/main.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_dep_file_expands_backslash_continued_deps() {
+        let root = TempDir::new().expect("tempdir");
+        let dep_path = root.path().join("app.d");
+        fs::write(&dep_path, "app.o: src/a.c \\\n  src/b.h\n").unwrap();
+        let deps = parse_dep_file(&dep_path).expect("parse_dep_file");
+        assert_eq!(deps, vec!["src/a.c".to_string(), "src/b.h".to_string()]);
+    }
+
+    #[test]
+    fn collect_build_inputs_expands_dep_file_entries() {
+        let root = TempDir::new().expect("tempdir");
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join("src/a.c"), "int main(){}").unwrap();
+        fs::write(root.path().join("app.d"), "app.o: src/a.c\n").unwrap();
+
+        let inputs = collect_build_inputs(root.path(), None, &["app.d".to_string()])
+            .expect("collect_build_inputs");
+        assert_eq!(inputs, vec![root.path().join("src/a.c")]);
+    }
+
+    #[test]
+    fn compute_digest_changes_when_a_file_changes() {
+        let root = TempDir::new().expect("tempdir");
+        let file = root.path().join("a.txt");
+        fs::write(&file, "one").unwrap();
+        let first = compute_digest(root.path(), &[file.clone()]).expect("compute_digest");
+        fs::write(&file, "two").unwrap();
+        let second = compute_digest(root.path(), &[file]).expect("compute_digest");
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn marker_round_trips_through_write_and_read() {
+        let root = TempDir::new().expect("tempdir");
+        let marker = root.path().join("target").join(".giftwrap-build-digest");
+        assert_eq!(read_marker(&marker), None);
+        write_marker(&marker, "abc123").expect("write_marker");
+        assert_eq!(read_marker(&marker), Some("abc123".to_string()));
+    }
+}
@@ -0,0 +1,70 @@
+//! Minimal `{{ name }}` substitution for templated Containerfiles, so a
+//! project can pin the build user/uid/gid/package flags into the image via
+//! `containerfile_template` instead of hand-editing a Containerfile per
+//! checkout.
+
+use std::collections::BTreeMap;
+
+/// Replace every `{{ name }}` token (whitespace around `name` is optional)
+/// with its value from `vars`. Unknown names, and unterminated `{{`, are
+/// left untouched so a template can mix in runtime-native syntax (e.g.
+/// `--build-arg` refs) without colliding with ours.
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            out.push_str("{{");
+            rest = after_open;
+            continue;
+        };
+        let name = after_open[..end].trim();
+        match vars.get(name) {
+            Some(value) => out.push_str(value),
+            None => {
+                out.push_str("{{");
+                out.push_str(&after_open[..end]);
+                out.push_str("}}");
+            }
+        }
+        rest = &after_open[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use std::collections::BTreeMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn render_substitutes_known_vars_with_and_without_spacing() {
+        let rendered = render(
+            "FROM {{image}}\nARG CTX={{ ctx }}\nUSER {{ user}}",
+            &vars(&[("image", "base:latest"), ("ctx", "abc123"), ("user", "dev")]),
+        );
+        assert_eq!(rendered, "FROM base:latest\nARG CTX=abc123\nUSER dev");
+    }
+
+    #[test]
+    fn render_leaves_unknown_tokens_untouched() {
+        let rendered = render("{{ mystery }}", &vars(&[]));
+        assert_eq!(rendered, "{{ mystery }}");
+    }
+
+    #[test]
+    fn render_leaves_unterminated_braces_untouched() {
+        let rendered = render("prefix {{ broken", &vars(&[]));
+        assert_eq!(rendered, "prefix {{ broken");
+    }
+}
@@ -0,0 +1,207 @@
+//! Integration-test harness for launching a real container and waiting for
+//! it to become ready, modeled on cargo's container test support (which
+//! spins up throwaway sshd/apache containers and polls until the service
+//! answers before running assertions). Feature-gated behind `test-support`
+//! since it shells out to a real container runtime - it's meant for
+//! downstream integration tests, not the shipped binary.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::exec::{self, ContainerId, ExecError};
+use crate::internal::{BuildSpec, ContainerSpec};
+use crate::runtime::Backend;
+
+/// Starting backoff between readiness polls; doubles each attempt up to
+/// `READY_POLL_BACKOFF_CAP`.
+const READY_POLL_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+const READY_POLL_BACKOFF_CAP: Duration = Duration::from_secs(1);
+
+/// Extra condition to wait for beyond "container is Running" - a container
+/// can report `Running` well before the service inside has actually bound a
+/// socket, so most tests need one of these rather than `None`.
+pub enum ReadyProbe {
+    /// Running state alone is enough.
+    None,
+    /// Ready as soon as a TCP handshake to `host:port` succeeds.
+    TcpConnect { host: String, port: u16 },
+    /// Like `TcpConnect`, but also requires the response to `path` to start
+    /// with `expect_prefix` (e.g. an HTTP status line) before the service
+    /// counts as ready.
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+        expect_prefix: String,
+    },
+}
+
+/// A running test container, built and launched from a `BuildSpec`/
+/// `ContainerSpec` pair, torn down (`stop` then `delete`) on drop. Build
+/// with `TestContainer::start`.
+pub struct TestContainer<'a> {
+    backend: &'a dyn Backend,
+    id: ContainerId,
+}
+
+impl<'a> TestContainer<'a> {
+    /// Build `build_spec`'s image, launch `run_spec` from it, and block
+    /// until `probe` reports ready or `timeout` elapses. On timeout, the
+    /// container's logs are captured and folded into the returned error so
+    /// a failing test shows why the service never came up; an error from
+    /// inspecting the container is returned as is, without logs.
+    pub fn start(
+        backend: &'a dyn Backend,
+        build_spec: &BuildSpec,
+        run_spec: &ContainerSpec,
+        probe: &ReadyProbe,
+        timeout: Duration,
+    ) -> Result<Self, ExecError> {
+        exec::build_image(backend, build_spec)?;
+        let id = exec::create(backend, run_spec)?;
+        exec::start(backend, &id)?;
+
+        let container = Self { backend, id };
+        container.wait_ready(probe, timeout)?;
+        Ok(container)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Resolve the host-side `host:port` the runtime published for
+    /// `container_port`, via `<runtime> port <id> <container_port>` - for
+    /// specs that publish with a random host port (e.g. `-p 127.0.0.1::80`)
+    /// and need to discover what was actually assigned.
+    pub fn published_port(&self, container_port: u16) -> Result<(String, u16), ExecError> {
+        let args = vec![
+            "port".to_string(),
+            self.id.clone(),
+            container_port.to_string(),
+        ];
+        let output = Command::new(self.backend.binary_name())
+            .args(&args)
+            .output()
+            .map_err(|err| ExecError::not_found("port", self.backend.binary_name(), err))?;
+        if !output.status.success() {
+            return Err(ExecError::probe_failed(
+                "port",
+                self.backend.binary_name(),
+                &args,
+                &output,
+            ));
+        }
+        parse_port_mapping(String::from_utf8_lossy(&output.stdout).trim())
+    }
+
+    fn wait_ready(&self, probe: &ReadyProbe, timeout: Duration) -> Result<(), ExecError> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = READY_POLL_INITIAL_BACKOFF;
+        loop {
+            if self.is_ready(probe)? {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                let logs = self.captured_logs().unwrap_or_default();
+                return Err(ExecError::probe_invalid(
+                    "wait_ready",
+                    format!(
+                        "Error: container {} did not become ready within {timeout:?}\nlogs:\n{logs}",
+                        self.id
+                    ),
+                ));
+            }
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(READY_POLL_BACKOFF_CAP);
+        }
+    }
+
+    fn is_ready(&self, probe: &ReadyProbe) -> Result<bool, ExecError> {
+        let state = exec::inspect_container(self.backend, &self.id)?;
+        if state.state.status != "running" {
+            return Ok(false);
+        }
+        Ok(match probe {
+            ReadyProbe::None => true,
+            ReadyProbe::TcpConnect { host, port } => {
+                TcpStream::connect((host.as_str(), *port)).is_ok()
+            }
+            ReadyProbe::Http {
+                host,
+                port,
+                path,
+                expect_prefix,
+            } => probe_http(host, *port, path, expect_prefix),
+        })
+    }
+
+    fn captured_logs(&self) -> Option<String> {
+        let mut logs = exec::logs(self.backend, &self.id, false).ok()?;
+        let mut buf = String::new();
+        logs.read_to_string(&mut buf).ok()?;
+        Some(buf)
+    }
+}
+
+impl Drop for TestContainer<'_> {
+    fn drop(&mut self) {
+        let _ = exec::stop(self.backend, &self.id, Some(Duration::from_secs(5)));
+        let _ = exec::delete(self.backend, &self.id, true);
+    }
+}
+
+fn probe_http(host: &str, port: u16, path: &str, expect_prefix: &str) -> bool {
+    let Ok(mut stream) = TcpStream::connect((host, port)) else {
+        return false;
+    };
+    let request = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+    let mut response = String::new();
+    if stream.read_to_string(&mut response).is_err() {
+        return false;
+    }
+    response.starts_with(expect_prefix)
+}
+
+/// Parse `<runtime> port`'s `host:port` output (e.g. `"0.0.0.0:55000"`).
+fn parse_port_mapping(text: &str) -> Result<(String, u16), ExecError> {
+    let (host, port) = text.rsplit_once(':').ok_or_else(|| {
+        ExecError::probe_invalid("port", format!("Error: unexpected runtime port output: {text}"))
+    })?;
+    let port = port.parse::<u16>().map_err(|err| {
+        ExecError::probe_invalid(
+            "port",
+            format!("Error: unexpected runtime port output: {text} ({err})"),
+        )
+    })?;
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_port_mapping;
+
+    #[test]
+    fn parse_port_mapping_splits_host_and_port() {
+        let (host, port) = parse_port_mapping("0.0.0.0:55000").expect("parse_port_mapping failed");
+        assert_eq!(host, "0.0.0.0");
+        assert_eq!(port, 55000);
+    }
+
+    #[test]
+    fn parse_port_mapping_rejects_missing_port() {
+        let err = parse_port_mapping("0.0.0.0")
+            .err()
+            .expect("expected parse_port_mapping to fail");
+        assert!(
+            err.to_string()
+                .starts_with("Error: unexpected runtime port output:"),
+            "unexpected error message: {err}"
+        );
+    }
+}